@@ -0,0 +1,54 @@
+use alloy_eips::eip4844::{Blob, Bytes48};
+use criterion::{criterion_group, criterion_main, Criterion};
+use exex::sequencer::{
+    coding::CodingScheme,
+    sequencer::{codec, encode_blob, process_blob_sidecar},
+};
+use futures_util::future::join_all;
+use rand::Rng;
+use reth::primitives::BlobTransactionSidecar;
+
+/// Blob limit of a block since Deneb
+const BLOBS_PER_BLOCK: usize = 6;
+
+fn sidecar(blob_count: usize) -> BlobTransactionSidecar {
+    let mut rng = rand::thread_rng();
+    let blobs = (0..blob_count)
+        .map(|_| {
+            let mut blob = Blob::ZERO;
+            rng.fill(&mut blob.0[..]);
+            blob
+        })
+        .collect();
+    BlobTransactionSidecar {
+        blobs,
+        commitments: vec![Bytes48::ZERO; blob_count],
+        proofs: vec![Bytes48::ZERO; blob_count],
+    }
+}
+
+fn encode(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let scheme = CodingScheme::default();
+
+    let blob = sidecar(1).blobs.remove(0);
+    let codec = codec(&scheme).unwrap();
+    c.bench_function("encode 1 blob", |b| {
+        b.iter(|| encode_blob(&codec, blob.as_slice(), &scheme).unwrap())
+    });
+
+    let block = sidecar(BLOBS_PER_BLOCK);
+    c.bench_function("encode block, 1 transaction of 6 blobs", |b| {
+        b.to_async(&runtime).iter(|| process_blob_sidecar(block.clone(), &scheme, None))
+    });
+
+    let transactions: Vec<_> = (0..BLOBS_PER_BLOCK).map(|_| sidecar(1)).collect();
+    c.bench_function("encode block, 6 transactions of 1 blob", |b| {
+        b.to_async(&runtime).iter(|| {
+            join_all(transactions.iter().map(|tx| process_blob_sidecar(tx.clone(), &scheme, None)))
+        })
+    });
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+use clap::{Parser, Subcommand};
+use exex::proto::{storage_node_client::StorageNodeClient, ScrubNowRequest};
+
+/// Maintenance commands for a running storage node
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Args {
+    /// Endpoint the storage node serves shards on
+    #[clap(long, default_value = "http://[::1]:50051")]
+    endpoint: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check every stored shard now and print the corrupted ones
+    ScrubNow,
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    let mut client = StorageNodeClient::connect(args.endpoint.clone()).await?;
+
+    match args.command {
+        Command::ScrubNow => {
+            let report = client.scrub_now(ScrubNowRequest {}).await?.into_inner();
+            println!(
+                "Checked {} shards ({} bytes), {} corrupted",
+                report.shards_checked,
+                report.bytes_checked,
+                report.corrupted.len()
+            );
+            for shard in report.corrupted {
+                println!("  shard {} of blob {}: {}", shard.index, shard.commitment, shard.reason);
+            }
+        }
+    }
+    Ok(())
+}
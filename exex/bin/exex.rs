@@ -1,164 +1,871 @@
-use exex::blobs::{fetch_blobs_for_block, BlobTransactionEvent};
+use alloy_eips::eip4844::Bytes48;
+use bytes::Bytes;
+use clap::Parser;
+use exex::beacon::{BeaconBlockResolver, BlockIdKind};
+use exex::blobs::{
+    fetch_blobs_for_block, http_client, reorged_blobs_for_block, BlobTransactionEvent,
+    BlockMetadata, MinedBlob, ReorgedBlob, SideCarError,
+};
+use exex::checkpoint::Checkpoint;
+use exex::config::{BlobsterConfig, ConfigArgs};
+use exex::dispatch::{blob_chunk, BlobHeader, NodeQueues, NotificationStream};
+use exex::distributed::DistributedBlobs;
+use exex::filter::BlobFilter;
+use exex::inclusion::{InclusionVerifier, ValidatorSet};
+use exex::kzg::{load_cell_setup, load_trusted_setup, CellSetup};
+use exex::outbox::{Ack, Outbox};
 use exex::proto::{
     remote_ex_ex_server::{RemoteExEx, RemoteExExServer},
-    BlobChunk, NodeOnlineRequest, NodeOnlineResponse, SubscribeRequest as ProtoSubscribeRequest,
+    AckRequest, AckResponse, BlobChunk, CorruptionReport, CorruptionResponse, DeregisterRequest,
+    DeregisterResponse, FilterCount, FilterStatsRequest, FilterStatsResponse, HeartbeatRequest,
+    HeartbeatResponse, ListNodesRequest, ListNodesResponse, NodeOnlineRequest, NodeOnlineResponse,
+    RetractionAck, SubscribeRequest as ProtoSubscribeRequest,
+};
+use exex::quarantine::Quarantine;
+use exex::registry::NodeRegistry;
+use exex::repair::{RepairCoordinator, RepairQueue};
+use exex::retry::{PendingBlocks, RetryPolicy};
+use exex::sequencer::{
+    coding::{CodingAlgorithm, CodingScheme},
+    placement::{place_missing_shards, place_shards},
+    sequencer::{process_blob_sidecar, BlobError, EncodedBlob, SequencerError},
 };
-use exex::sequencer::sequencer::process_blob_sidecar;
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
+use futures_util::{future::join_all, StreamExt};
+use reth::primitives::{kzg::KzgSettings, Address, SealedBlockWithSenders, B256};
+use reth::providers::{BlockIdReader, BlockNumReader, BlockReader, TransactionVariant};
 use reth_exex::{ExExContext, ExExEvent};
 use reth_node_api::FullNodeComponents;
 use reth_node_ethereum::EthereumNode;
 use reth_tracing::tracing::info;
-use tokio::sync::{broadcast, mpsc};
-use tokio_stream::wrappers::ReceiverStream;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::mpsc,
+    time::{interval, Duration},
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tonic::{transport::Server, Request, Response, Status};
 
-const NUM_NODES: usize = 3;
-
-#[derive(Debug, Clone)]
-pub enum ExExNotification {
-    BlobChunk { node_id: u32, chunk_index: u32, chunk: Vec<u8>, name: String },
-    NodeOnline { node_id: u32 },
-}
+/// Deliveries of a shard to the same node before it is re-placed on another one
+const DELIVERY_ATTEMPTS_PER_NODE: u32 = 3;
+/// Pending blocks whose sidecars are fetched at the same time
+const RETRY_CONCURRENCY: usize = 4;
 
 #[derive(Debug)]
 struct ExExService {
-    notifications: broadcast::Sender<ExExNotification>,
-    online_nodes: broadcast::Sender<u32>,
+    queues: NodeQueues,
+    registry: NodeRegistry,
+    outbox: Arc<Mutex<Outbox>>,
+    repairs: Arc<Mutex<RepairQueue>>,
+    filter: BlobFilter,
 }
 
 #[tonic::async_trait]
 impl RemoteExEx for ExExService {
-    type SubscribeStream = ReceiverStream<Result<BlobChunk, Status>>;
+    type SubscribeStream = NotificationStream;
 
     async fn subscribe(
         &self,
-        _request: Request<ProtoSubscribeRequest>,
+        request: Request<ProtoSubscribeRequest>,
     ) -> Result<Response<Self::SubscribeStream>, Status> {
-        let (tx, rx) = mpsc::channel(1000);
-        //println!("Subscribing to notifications...");
-        let mut notifications = self.notifications.subscribe();
-        //println!("Subscribed to notifications");
-        tokio::spawn(async move {
-            while let Ok(notification) = notifications.recv().await {
-                match notification {
-                    ExExNotification::BlobChunk {
-                        node_id: chunk_node_id,
-                        chunk_index,
-                        chunk,
-                        name,
-                    } => {
-                        //info!("Received blob chunk from notification");
-                        info!(
-                            "Sending chunk {} (name: {}) to node {}",
-                            chunk_index, name, chunk_node_id
-                        );
-
-                        let blob_chunk =
-                            BlobChunk { node_id: chunk_node_id, chunk_index, chunk, name };
-                        if tx.send(Ok(blob_chunk)).await.is_err() {
-                            eprintln!("Failed to send blob chunk to gRPC stream");
-                            break;
-                        } else {
-                        }
+        let node_id = request.into_inner().node_id;
+        info!("Node {} subscribed to notifications", node_id);
+        Ok(Response::new(self.queues.subscribe(node_id)))
+    }
+
+    async fn notify_online(
+        &self,
+        request: Request<NodeOnlineRequest>,
+    ) -> Result<Response<NodeOnlineResponse>, Status> {
+        let request = request.into_inner();
+        info!("Node {} is online at {}", request.node_id, request.endpoint);
+        self.registry
+            .register(request.node_id, request.endpoint, request.capacity, request.public_key)
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        let response =
+            NodeOnlineResponse { message: format!("Node {} is online", request.node_id) };
+        Ok(Response::new(response))
+    }
+
+    async fn heartbeat(
+        &self,
+        request: Request<HeartbeatRequest>,
+    ) -> Result<Response<HeartbeatResponse>, Status> {
+        let request = request.into_inner();
+        // Registering again on an unknown heartbeat would undo the deregistration
+        if self.registry.is_deregistered(request.node_id) {
+            return Err(Status::failed_precondition(format!(
+                "node {} is deregistered",
+                request.node_id
+            )));
+        }
+        let registered = self.registry.heartbeat(request.node_id, request.used_bytes);
+        if !registered {
+            info!("Heartbeat from unregistered node {}", request.node_id);
+        }
+        Ok(Response::new(HeartbeatResponse { registered }))
+    }
+
+    async fn list_nodes(
+        &self,
+        _request: Request<ListNodesRequest>,
+    ) -> Result<Response<ListNodesResponse>, Status> {
+        Ok(Response::new(ListNodesResponse { nodes: self.registry.list() }))
+    }
+
+    async fn deregister(
+        &self,
+        request: Request<DeregisterRequest>,
+    ) -> Result<Response<DeregisterResponse>, Status> {
+        let node_id = request.into_inner().node_id;
+        let removed =
+            self.registry.deregister(node_id).map_err(|e| Status::internal(e.to_string()))?;
+        if removed {
+            info!("Node {} deregistered", node_id);
+            // A node that left for good never acknowledges the retractions sent to it
+            let outbox = self.outbox.clone();
+            tokio::task::spawn_blocking(move || outbox.lock().unwrap().forget_retractions(node_id))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .map_err(|e| Status::internal(e.to_string()))?;
+        }
+        Ok(Response::new(DeregisterResponse { removed }))
+    }
+
+    async fn ack(&self, request: Request<AckRequest>) -> Result<Response<AckResponse>, Status> {
+        let request = request.into_inner();
+        let hash = B256::try_from(request.hash.as_slice())
+            .map_err(|_| Status::invalid_argument("hash must be 32 bytes"))?;
+        // Settling a shard writes to disk, which must not block the runtime serving other nodes
+        let outbox = self.outbox.clone();
+        let (name, index, node_id) =
+            (request.commitment.clone(), request.chunk_index, request.node_id);
+        let ack = tokio::task::spawn_blocking(move || {
+            outbox.lock().unwrap().ack(&name, index, node_id, hash)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::internal(e.to_string()))?;
+
+        match ack {
+            Ack::Accepted { blob_complete: true } => {
+                info!("All chunks of blob {} are stored", request.commitment)
+            }
+            Ack::Accepted { blob_complete: false } => {}
+            Ack::Unknown => info!(
+                "Node {} acknowledged chunk {} of blob {} it was not sent",
+                request.node_id, request.chunk_index, request.commitment
+            ),
+            Ack::HashMismatch => eprintln!(
+                "Node {} stored chunk {} of blob {} with the wrong hash",
+                request.node_id, request.chunk_index, request.commitment
+            ),
+        }
+        Ok(Response::new(AckResponse { accepted: matches!(ack, Ack::Accepted { .. }) }))
+    }
+
+    async fn ack_retraction(
+        &self,
+        request: Request<RetractionAck>,
+    ) -> Result<Response<AckResponse>, Status> {
+        let request = request.into_inner();
+        let block_hash = request
+            .block_hash
+            .parse::<B256>()
+            .map_err(|_| Status::invalid_argument("block hash must be 32 bytes"))?;
+        let outbox = self.outbox.clone();
+        let (name, node_id) = (request.commitment.clone(), request.node_id);
+        let accepted = tokio::task::spawn_blocking(move || {
+            outbox.lock().unwrap().ack_retraction(&name, block_hash, node_id)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::internal(e.to_string()))?;
+        if accepted {
+            info!("Node {} retracted blob {}", request.node_id, request.commitment);
+        }
+        Ok(Response::new(AckResponse { accepted }))
+    }
+
+    async fn report_corruption(
+        &self,
+        request: Request<CorruptionReport>,
+    ) -> Result<Response<CorruptionResponse>, Status> {
+        let request = request.into_inner();
+        eprintln!(
+            "Node {} lost chunk {} of blob {}: {}",
+            request.node_id, request.chunk_index, request.commitment, request.reason
+        );
+        let repairs = self.repairs.clone();
+        tokio::task::spawn_blocking(move || {
+            repairs.lock().unwrap().add(
+                &request.commitment,
+                request.chunk_index,
+                request.node_id,
+                request.reason,
+            )
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(CorruptionResponse {}))
+    }
+
+    async fn filter_stats(
+        &self,
+        _request: Request<FilterStatsRequest>,
+    ) -> Result<Response<FilterStatsResponse>, Status> {
+        let stats = self.filter.stats();
+        let rejected = stats
+            .rejected
+            .iter()
+            .map(|(filter, rejected)| FilterCount {
+                filter: filter.to_string(),
+                rejected: *rejected,
+            })
+            .collect();
+        Ok(Response::new(FilterStatsResponse {
+            passed: stats.passed,
+            passed_blobs: stats.passed_blobs,
+            rejected,
+        }))
+    }
+}
+
+/// Fetches and verifies the sidecars of the blob transactions of a block that pass the filter
+#[derive(Debug, Clone)]
+struct SidecarFetcher {
+    client: reqwest::Client,
+    beacon_url: String,
+    resolver: BeaconBlockResolver,
+    kzg_settings: Arc<KzgSettings>,
+    inclusion: Arc<InclusionVerifier>,
+    filter: BlobFilter,
+}
+
+impl SidecarFetcher {
+    async fn fetch(&self, block: &SealedBlockWithSenders) -> Result<Vec<MinedBlob>, SideCarError> {
+        let txs: Vec<_> = block
+            .transactions()
+            .filter(|tx| tx.is_eip4844())
+            .map(|tx| (tx.clone(), tx.blob_versioned_hashes().unwrap().len()))
+            .collect();
+        if txs.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Sidecars are matched to transactions by position, so every blob transaction is fetched
+        // and only the blobs of admitted ones are kept
+        let admitted: HashSet<B256> = block
+            .transactions_with_sender()
+            .filter(|(sender, tx)| tx.is_eip4844() && self.filter.admit(**sender, tx))
+            .map(|(_, tx)| tx.hash())
+            .collect();
+        if admitted.is_empty() {
+            println!("No blob transaction of block {} passed the filter", block.number);
+            return Ok(Vec::new());
+        }
+        println!("Block Hash: {:?}", block.hash());
+
+        let block_root = self.resolver.resolve(block).await?;
+        let blob_transactions = fetch_blobs_for_block(
+            &self.client,
+            &self.beacon_url,
+            block_root,
+            block.clone(),
+            txs,
+            &self.kzg_settings,
+            &self.inclusion,
+        )
+        .await?;
+        println!("Found {} blob transactions", blob_transactions.len());
+
+        Ok(blob_transactions
+            .into_iter()
+            .filter_map(|event| match event {
+                BlobTransactionEvent::Mined(mined)
+                    if admitted.contains(&mined.transaction.hash) =>
+                {
+                    Some(mined)
+                }
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+/// Fetches, verifies, encodes and distributes the blobs of canonical blocks
+struct BlobProcessor {
+    config: BlobsterConfig,
+    fetcher: SidecarFetcher,
+    // Only loaded for field element schemes, whose shards are sent with a cell proof
+    cell_setup: Option<Arc<CellSetup>>,
+    queues: NodeQueues,
+    registry: NodeRegistry,
+    outbox: Arc<Mutex<Outbox>>,
+    quarantine: Quarantine,
+    /// Shared with the repair coordinator, which only repairs canonical blobs
+    distributed: Arc<Mutex<DistributedBlobs>>,
+}
+
+impl BlobProcessor {
+    fn new(
+        config: BlobsterConfig,
+        queues: NodeQueues,
+        registry: NodeRegistry,
+        outbox: Arc<Mutex<Outbox>>,
+        distributed: Arc<Mutex<DistributedBlobs>>,
+        filter: BlobFilter,
+    ) -> eyre::Result<Self> {
+        let resolver = BeaconBlockResolver::new(&config.beacon_url, config.block_id);
+        let kzg_settings = load_trusted_setup(config.trusted_setup.as_deref())?;
+        let cell_setup = (config.coding.algorithm == CodingAlgorithm::FieldElementFft)
+            .then(|| load_cell_setup(config.trusted_setup.as_deref()))
+            .transpose()?;
+        let quarantine = Quarantine::load(&config.data_dir.join("quarantine.json"))?;
+        let inclusion = InclusionVerifier {
+            check_block_root: config.block_id == BlockIdKind::BeaconRoot,
+            validators: config.validator_set.as_deref().map(ValidatorSet::load).transpose()?,
+        };
+        let fetcher = SidecarFetcher {
+            client: http_client(),
+            beacon_url: config.beacon_url.clone(),
+            resolver,
+            kzg_settings,
+            inclusion: Arc::new(inclusion),
+            filter,
+        };
+        Ok(Self { config, fetcher, cell_setup, queues, registry, outbox, quarantine, distributed })
+    }
+
+    async fn process_block(&mut self, block: &SealedBlockWithSenders) -> Result<(), SideCarError> {
+        let mined = self.fetcher.fetch(block).await?;
+        self.distribute_block(mined).await
+    }
+
+    /// Encode and distribute the fetched blob transactions of a block
+    async fn distribute_block(&mut self, mined: Vec<MinedBlob>) -> Result<(), SideCarError> {
+        // Encode every transaction of the block at once, so all its blobs share the encoder pool
+        let scheme = self.config.coding;
+        let encoding =
+            mined.into_iter().map(|m| encode_transaction(m, scheme, self.cell_setup.clone()));
+        for encoded in join_all(encoding).await {
+            self.distribute_transaction(encoded).await?;
+        }
+        Ok(())
+    }
+
+    async fn retract_block(&mut self, block: &SealedBlockWithSenders) {
+        println!("Reverted Block Hash: {:?}", block.hash());
+        for event in reorged_blobs_for_block(block) {
+            if let Err(e) = self.handle_blob_event(event).await {
+                eprintln!("Error retracting blobs of block {}: {}", block.number, e);
+            }
+        }
+    }
+
+    async fn handle_blob_event(&mut self, event: BlobTransactionEvent) -> Result<(), SideCarError> {
+        match event {
+            BlobTransactionEvent::Mined(mined) => {
+                let encoded =
+                    encode_transaction(mined, self.config.coding, self.cell_setup.clone()).await;
+                self.distribute_transaction(encoded).await?;
+            }
+            BlobTransactionEvent::Reorged(reorged) => {
+                println!("Reorged blob transaction: {:?}", reorged);
+                let ReorgedBlob { transaction_hash, block_metadata: block } = reorged;
+                let names =
+                    self.distributed.lock().unwrap().get(block.block_number, transaction_hash);
+                let Some(names) = names else {
+                    return Ok(());
+                };
+                // Any registered node may hold shards of the blob, re-placement moves them around
+                let mut nodes: Vec<u32> =
+                    self.registry.list().into_iter().map(|node| node.node_id).collect();
+                nodes.extend(self.queues.subscribed());
+                nodes.sort_unstable();
+                nodes.dedup();
+                for name in &names {
+                    info!("Retracting blob {} from orphaned block {}", name, block.block_hash);
+                    // Kept in the outbox until every node acknowledges it, see `redeliver_expired`
+                    self.outbox
+                        .lock()
+                        .unwrap()
+                        .retract(name, block.block_hash, &nodes)
+                        .map_err(|e| SideCarError::DistributionError(e.to_string()))?;
+                    for node_id in &nodes {
+                        self.queues.send_retraction(*node_id, name, block.block_hash);
                     }
-                    ExExNotification::NodeOnline { node_id } => {
-                        println!("Received notification: Node {} is online", node_id);
+                }
+                self.distributed
+                    .lock()
+                    .unwrap()
+                    .take(block.block_number, block.block_hash, transaction_hash)
+                    .map_err(|e| SideCarError::DistributionError(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Distribute the encoded blobs of a transaction, quarantining those that failed to encode
+    async fn distribute_transaction(
+        &mut self,
+        encoded: EncodedTransaction,
+    ) -> Result<(), SideCarError> {
+        let EncodedTransaction { transaction_hash, block, sender, commitments, blobs } = encoded;
+
+        // Retrying blobs that cannot be encoded would fail again
+        let blobs = match blobs {
+            Ok(blobs) => blobs,
+            Err(e) => {
+                for commitment in commitments {
+                    let name = commitment.to_string();
+                    self.quarantine_blob(&name, &block, transaction_hash, &e)?;
+                }
+                return Ok(());
+            }
+        };
+
+        // Each blob is placed and stored under its own commitment
+        let mut names = Vec::with_capacity(blobs.len());
+        let mut failed = Ok(());
+        for blob in blobs {
+            match blob {
+                Ok(blob) => {
+                    let name = blob.commitment.to_string();
+                    let header = BlobHeader {
+                        name: &name,
+                        scheme: self.config.coding,
+                        block_number: block.block_number,
+                        block_hash: block.block_hash,
+                        sender,
+                    };
+                    failed = self.distribute_blob(&header, blob).await;
+                    if failed.is_err() {
+                        break;
                     }
+                    names.push(name);
+                }
+                Err(e) => {
+                    let name = e.commitment.to_string();
+                    self.quarantine_blob(&name, &block, transaction_hash, &e.source)?
                 }
             }
+        }
+        // Blobs sent before a failure are recorded too, so a reorg before the retry retracts them
+        if !names.is_empty() {
+            self.distributed
+                .lock()
+                .unwrap()
+                .add(block.block_number, transaction_hash, names)
+                .map_err(|e| SideCarError::DistributionError(e.to_string()))?;
+        }
+        failed
+    }
+
+    fn quarantine_blob(
+        &mut self,
+        name: &str,
+        block: &BlockMetadata,
+        transaction_hash: B256,
+        reason: &SequencerError,
+    ) -> Result<(), SideCarError> {
+        eprintln!("Quarantining blob {}: {}", name, reason);
+        self.quarantine
+            .add(name, block.block_number, block.block_hash, transaction_hash, reason.to_string())
+            .map_err(|e| SideCarError::DistributionError(e.to_string()))
+    }
+
+    /// Place the shards of a blob and send each to its node
+    async fn distribute_blob(
+        &mut self,
+        header: &BlobHeader<'_>,
+        blob: EncodedBlob,
+    ) -> Result<(), SideCarError> {
+        let nodes = self.registry.placeable_nodes();
+        let name = header.name;
+        let Some(placement) =
+            place_shards(name, blob.shards.len(), header.scheme.parity_shards, &nodes)
+        else {
+            return Err(SideCarError::DistributionError(
+                "no live storage nodes with room left".to_string(),
+            ));
+        };
+        if !placement.tolerates_node_loss {
+            eprintln!(
+                "Only {} storage nodes, losing one of them may lose blob {}",
+                nodes.len(),
+                name
+            );
+        }
+
+        let used: HashSet<u32> = placement.nodes.iter().copied().collect();
+        println!("Sending {} shards of blob {} to {} nodes", blob.shards.len(), name, used.len());
+        // Once in the outbox, shards are delivered until their node acknowledges them. Adding
+        // writes every shard to disk, so it stays off the async workers
+        let outbox = self.outbox.clone();
+        let (owned_name, placed) = (name.to_string(), placement.nodes.clone());
+        let BlobHeader { scheme, block_number, block_hash, sender, .. } = *header;
+        let blob = tokio::task::spawn_blocking(move || {
+            let header = BlobHeader { name: &owned_name, scheme, block_number, block_hash, sender };
+            outbox.lock().unwrap().add(&header, &blob, &placed).map(|()| blob)
+        })
+        .await
+        .map_err(|e| SideCarError::DistributionError(e.to_string()))?
+        .map_err(|e| SideCarError::DistributionError(e.to_string()))?;
+        let chunks = blob.shards.iter().enumerate().map(|(chunk_index, chunk)| {
+            blob_chunk(
+                header,
+                chunk_index as u32,
+                placement.nodes[chunk_index],
+                chunk.clone(),
+                blob.proofs.get(chunk_index).copied(),
+                Some(&blob.tree.proof(chunk_index)),
+            )
         });
+        self.queues.send_chunks(name, chunks);
+        Ok(())
+    }
+
+    /// Deliver shards and retractions again whose node did not acknowledge them in time
+    ///
+    /// A shard moves to another live node once its node is down or ignored it too many times.
+    fn redeliver_expired(&mut self) -> std::io::Result<()> {
+        let timeout = Duration::from_secs(self.config.ack_timeout_secs);
+        let mut outbox = self.outbox.lock().unwrap();
+        for retraction in outbox.redeliver_retractions(timeout)? {
+            self.queues.send_retraction(
+                retraction.node_id,
+                &retraction.name,
+                retraction.block_hash,
+            );
+        }
+        let expired = outbox.expired(timeout);
+        if expired.is_empty() {
+            return Ok(());
+        }
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+        // Stuck shards move off their node, placed around the shards their blob has elsewhere
+        let live_nodes = self.registry.live_nodes();
+        let placeable = self.registry.placeable_nodes();
+        let mut stuck: BTreeMap<&str, Vec<(u32, u32)>> = BTreeMap::new();
+        for (name, chunk_index, shard) in &expired {
+            if shard.attempts >= DELIVERY_ATTEMPTS_PER_NODE || !live_nodes.contains(&shard.node_id)
+            {
+                stuck.entry(name.as_str()).or_default().push((*chunk_index, shard.node_id));
+            }
+        }
+        let mut moves = HashMap::new();
+        for (name, shards) in stuck {
+            // Expired shards are listed from the outbox, so their blob is still in it
+            let Some(header) = outbox.header(name) else { continue };
+            let indices: Vec<u32> = shards.iter().map(|(index, _)| *index).collect();
+            let candidates: Vec<u32> = placeable
+                .iter()
+                .copied()
+                .filter(|node_id| !shards.iter().any(|(_, stuck_on)| stuck_on == node_id))
+                .collect();
+            let missing: Vec<usize> = indices.iter().map(|index| *index as usize).collect();
+            let Some(placed) = place_missing_shards(
+                name,
+                &missing,
+                header.scheme.total_shards(),
+                header.scheme.parity_shards,
+                &candidates,
+                &outbox.shards_per_node(name, &indices),
+            ) else {
+                continue;
+            };
+            for (index, node_id) in placed {
+                moves.insert((name.to_string(), index as u32), node_id);
+            }
+        }
+
+        // Shards to send again and how many of them moved, by blob
+        let mut deliveries: BTreeMap<String, (Vec<BlobChunk>, usize)> = BTreeMap::new();
+        for (name, chunk_index, shard) in expired {
+            let Some(header) = outbox.header(&name) else { continue };
+            let node_id = moves.get(&(name.clone(), chunk_index)).copied().unwrap_or(shard.node_id);
+
+            let Some(chunk) = outbox.redeliver(&name, chunk_index, node_id)? else { continue };
+            let (chunks, moved) = deliveries.entry(name.clone()).or_default();
+            *moved += usize::from(node_id != shard.node_id);
+            chunks.push(blob_chunk(
+                &header,
+                chunk_index,
+                node_id,
+                Bytes::from(chunk),
+                shard.proof,
+                shard.merkle.as_ref(),
+            ));
+        }
+        drop(outbox);
+
+        for (name, (chunks, moved)) in deliveries {
+            println!(
+                "Redelivering {} shards of blob {}, {} of them to other nodes",
+                chunks.len(),
+                name,
+                moved
+            );
+            self.queues.send_chunks(&name, chunks);
+        }
+        Ok(())
     }
+}
 
-    async fn notify_online(
-        &self,
-        request: Request<NodeOnlineRequest>,
-    ) -> Result<Response<NodeOnlineResponse>, Status> {
-        let node_id = request.into_inner().node_id;
-        info!("Node {} is online", node_id);
+/// Blobs of a transaction after encoding
+struct EncodedTransaction {
+    transaction_hash: B256,
+    block: BlockMetadata,
+    sender: Option<Address>,
+    commitments: Vec<Bytes48>,
+    blobs: Result<Vec<Result<EncodedBlob, BlobError>>, SequencerError>,
+}
 
-        let response = NodeOnlineResponse { message: format!("Node {} is online", node_id) };
-        Ok(Response::new(response))
+async fn encode_transaction(
+    mined: MinedBlob,
+    scheme: CodingScheme,
+    cell_setup: Option<Arc<CellSetup>>,
+) -> EncodedTransaction {
+    let MinedBlob { transaction, block_metadata, sender } = mined;
+    let commitments = transaction.sidecar.commitments.clone();
+    EncodedTransaction {
+        transaction_hash: transaction.hash,
+        block: block_metadata,
+        sender,
+        commitments,
+        blobs: process_blob_sidecar(transaction.sidecar, &scheme, cell_setup).await,
+    }
+}
+
+type FetchedBlock = (SealedBlockWithSenders, Result<Vec<MinedBlob>, SideCarError>);
+
+/// Fetch the sidecars of the blocks sent to the returned channel off the notification loop, a
+/// few at a time, reporting each outcome on the returned receiver
+fn spawn_retries(
+    fetcher: SidecarFetcher,
+) -> (mpsc::UnboundedSender<SealedBlockWithSenders>, mpsc::UnboundedReceiver<FetchedBlock>) {
+    let (block_tx, block_rx) = mpsc::unbounded_channel();
+    let (fetched_tx, fetched_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut fetches = UnboundedReceiverStream::new(block_rx)
+            .map(move |block: SealedBlockWithSenders| {
+                let fetcher = fetcher.clone();
+                async move {
+                    let fetched = fetcher.fetch(&block).await;
+                    (block, fetched)
+                }
+            })
+            .buffer_unordered(RETRY_CONCURRENCY);
+        while let Some(fetched) = fetches.next().await {
+            if fetched_tx.send(fetched).is_err() {
+                break;
+            }
+        }
+    });
+    (block_tx, fetched_rx)
+}
+
+/// Queue a block whose sidecars could not be fetched, unless the error is fatal
+///
+/// Blocks whose blobs could not be handed to storage nodes are kept until nodes take them.
+fn record_failure(
+    pending: &mut PendingBlocks,
+    policy: &RetryPolicy,
+    block: &SealedBlockWithSenders,
+    error: SideCarError,
+) -> std::io::Result<()> {
+    if let SideCarError::DistributionError(_) = error {
+        pending.postpone(block.number, block.hash(), policy)?;
+        eprintln!("Failed to distribute blobs of block {}, will retry: {}", block.number, error);
+        return Ok(());
+    }
+    if !error.is_retryable() {
+        eprintln!("Giving up on block {}: {:?}", block.number, error);
+        pending.remove(block.number)?;
+        return Ok(());
+    }
+    if pending.record_failure(block.number, block.hash(), policy)? {
+        println!("Sidecars of block {} not available yet, will retry: {}", block.number, error);
+    } else {
+        eprintln!("Giving up on block {} after retries: {:?}", block.number, error);
+    }
+    Ok(())
+}
+
+/// Log an error the notification loop recovers from on a later tick
+///
+/// State stores are written whole, so a failed write is made up for by the next one.
+fn log_transient<T, E: std::fmt::Display>(action: &str, result: Result<T, E>) {
+    if let Err(e) = result {
+        eprintln!("Failed to {}, will retry: {}", action, e);
     }
 }
 
 async fn exex<Node: FullNodeComponents>(
     mut ctx: ExExContext<Node>,
-    notifications: broadcast::Sender<ExExNotification>,
+    config: BlobsterConfig,
+    queues: NodeQueues,
+    registry: NodeRegistry,
+    outbox: Arc<Mutex<Outbox>>,
+    distributed: Arc<Mutex<DistributedBlobs>>,
+    filter: BlobFilter,
 ) -> eyre::Result<()> {
-    let mut rng = StdRng::from_entropy();
-    while let Some(notification) = ctx.notifications.recv().await {
-        if let Some(committed_chain) = notification.committed_chain() {
-            let events = committed_chain
-                // Get all blocks and receipts
-                .blocks_and_receipts()
-                // Get all receipts
-                .flat_map(|(block, receipts)| {
-                    block
-                        .body
-                        .iter()
-                        .zip(receipts.iter().flatten())
-                        .map(move |receipt| (block.clone(), receipt.clone()))
-                        .collect::<Vec<_>>()
-                });
-            for (block, _) in events {
-                let txs: Vec<_> = block
-                    .transactions()
-                    .filter(|tx| tx.is_eip4844())
-                    .map(|tx| (tx.clone(), tx.blob_versioned_hashes().unwrap().len()))
-                    .collect();
-                println!("Block Hash: {:?}", block.hash());
-                match fetch_blobs_for_block(block.hash(), block, txs).await {
-                    Ok(blob_transactions) => {
-                        println!("Found {} blob transactions", blob_transactions.len());
-                        for blob_transaction in blob_transactions {
-                            match blob_transaction {
-                                BlobTransactionEvent::Mined(mined) => {
-                                    let commitment =
-                                        mined.transaction.sidecar.commitments[0].clone();
-
-                                    match process_blob_sidecar(mined.transaction.sidecar).await {
-                                        Ok(chunks) => {
-                                            // Distribute each chunk randomly
-                                            for (chunk_index, chunk) in
-                                                chunks.into_iter().enumerate()
-                                            {
-                                                let node_id = rng.gen_range(1..=NUM_NODES) as u32;
-                                                let notification = ExExNotification::BlobChunk {
-                                                    node_id,
-                                                    chunk_index: chunk_index as u32,
-                                                    chunk,
-                                                    name: commitment.to_string(),
-                                                };
-                                                println!(
-                                                    "Sending chunk {} to node {}",
-                                                    chunk_index, node_id
-                                                );
-                                                if let Err(_e) = notifications.send(notification) {
-                                                    eprintln!("Failed to send chunk");
-                                                }
-                                            }
-                                        }
-                                        Err(_e) => {
-                                            eprintln!("Error processing blob sidecar:");
-                                        }
-                                    }
-                                }
-                                BlobTransactionEvent::Reorged(reorged) => {
-                                    println!("Reorged blob transaction: {:?}", reorged);
-                                }
-                            }
+    let policy = config.retry.clone();
+    let mut pending = PendingBlocks::load(&config.data_dir.join("pending_blocks.json"))?;
+    let mut checkpoint = Checkpoint::load(&config.data_dir.join("checkpoint.json"))?;
+    let backfill_from = config.backfill_from.or(checkpoint.block_number().map(|n| n + 1));
+    let mut processor = BlobProcessor::new(config, queues, registry, outbox, distributed, filter)?;
+    let (retry_tx, mut retry_rx) = spawn_retries(processor.fetcher.clone());
+    // Pending blocks whose sidecars are being fetched
+    let mut retrying = HashSet::new();
+    let mut retry_interval = interval(Duration::from_secs(1));
+    let mut redelivery_interval = interval(Duration::from_secs(1));
+    let mut tip = checkpoint.block_number();
+    let mut finished_height = checkpoint.block_number();
+
+    // Catch up on blocks processed while the ExEx was down
+    if let Some(from) = backfill_from {
+        let best = ctx.provider().best_block_number()?;
+        println!("Backfilling blocks {} to {}", from, best);
+        for block_number in from..=best {
+            let Some(block) = ctx
+                .provider()
+                .sealed_block_with_senders(block_number.into(), TransactionVariant::WithHash)?
+            else {
+                continue;
+            };
+            processor.fetcher.resolver.observe(&block);
+            processor.fetcher.filter.count(&block);
+            if let Err(e) = processor.process_block(&block).await {
+                record_failure(&mut pending, &policy, &block, e)?;
+            }
+        }
+        tip = tip.max(Some(best));
+    }
+
+    loop {
+        tokio::select! {
+            notification = ctx.notifications.recv() => {
+                let Some(notification) = notification else { break };
+
+                // A reorg reverts the old chain before committing the new one, so retract first
+                if let Some(reverted_chain) = notification.reverted_chain() {
+                    for block in reverted_chain.blocks().values().rev() {
+                        log_transient("drop a reverted pending block", pending.remove(block.number));
+                        processor.retract_block(block).await;
+                    }
+                }
+
+                if let Some(committed_chain) = notification.committed_chain() {
+                    // Children carry the beacon roots of their parents
+                    for block in committed_chain.blocks_iter() {
+                        processor.fetcher.resolver.observe(block);
+                    }
+                    for block in committed_chain.blocks_iter() {
+                        // Counted here rather than when fetched, as retries fetch blocks again
+                        processor.fetcher.filter.count(block);
+                        if let Err(e) = processor.process_block(block).await {
+                            let recorded = record_failure(&mut pending, &policy, block, e);
+                            log_transient("record a failed block", recorded);
+                        }
+                    }
+                    tip = Some(committed_chain.tip().number);
+                }
+            }
+            _ = retry_interval.tick(), if !pending.is_empty() => {
+                for (block_number, pending_block) in pending.due() {
+                    if !retrying.insert(block_number) {
+                        continue;
+                    }
+                    let block = match ctx.provider().sealed_block_with_senders(
+                        pending_block.block_hash.into(),
+                        TransactionVariant::WithHash,
+                    ) {
+                        Ok(block) => block,
+                        Err(e) => {
+                            eprintln!("Failed to read pending block {}: {}", block_number, e);
+                            retrying.remove(&block_number);
+                            continue;
                         }
+                    };
+                    let Some(block) = block else {
+                        eprintln!("Pending block {} is no longer available", block_number);
+                        log_transient("drop a pending block", pending.remove(block_number));
+                        retrying.remove(&block_number);
+                        continue;
+                    };
+                    if let Err(e) = retry_tx.send(block) {
+                        eprintln!("Failed to queue block {} for a retry: {}", block_number, e);
+                        retrying.remove(&block_number);
                     }
-                    Err(e) => {
-                        eprintln!("Error fetching blob transactions: {:?}", e);
+                }
+            }
+            Some((block, fetched)) = retry_rx.recv() => {
+                retrying.remove(&block.number);
+                // The block may have been reverted while its sidecars were being fetched
+                let attempts = pending
+                    .get(block.number)
+                    .filter(|pending_block| pending_block.block_hash == block.hash())
+                    .map(|pending_block| pending_block.attempts);
+                if let Some(attempts) = attempts {
+                    let distributed = match fetched {
+                        Ok(mined) => processor.distribute_block(mined).await,
+                        Err(e) => Err(e),
+                    };
+                    match distributed {
+                        Ok(()) => {
+                            println!(
+                                "Fetched sidecars of block {} after {} attempts",
+                                block.number,
+                                attempts + 1
+                            );
+                            log_transient("drop a fetched block", pending.remove(block.number));
+                        }
+                        Err(e) => {
+                            let recorded = record_failure(&mut pending, &policy, &block, e);
+                            log_transient("record a failed block", recorded);
+                        }
                     }
                 }
             }
-            ctx.events.send(ExExEvent::FinishedHeight(committed_chain.tip().number))?;
+            _ = redelivery_interval.tick() => {
+                log_transient("redeliver expired shards", processor.redeliver_expired());
+            }
+        }
+
+        // Blocks still waiting for their sidecars, or for storage nodes to acknowledge their
+        // shards, must not be pruned
+        let unfinished = pending
+            .lowest()
+            .into_iter()
+            .chain(processor.outbox.lock().unwrap().lowest_block())
+            .min();
+        let height = match (tip, unfinished) {
+            (Some(tip), Some(lowest)) => Some(tip.min(lowest.saturating_sub(1))),
+            (tip, _) => tip,
+        };
+        // The finished height never goes back, not even when a reorg lowers the tip
+        if let Some(height) = height.filter(|height| finished_height < Some(*height)) {
+            // Saved again after the next event when it fails, the node is only told once saved
+            if let Err(e) = checkpoint.save(height) {
+                eprintln!("Failed to save checkpoint at block {}, will retry: {}", height, e);
+                continue;
+            }
+            finished_height = Some(height);
+            // Only fails once the node stopped listening, i.e. is shutting down
+            ctx.events.send(ExExEvent::FinishedHeight(height))?;
+            // Finalized blocks are never reorged out, so their blobs will not be retracted
+            match ctx.provider().finalized_block_number() {
+                Ok(finalized) => log_transient(
+                    "prune distributed blobs",
+                    processor.distributed.lock().unwrap().prune(finalized.unwrap_or(height)),
+                ),
+                Err(e) => eprintln!("Failed to read the finalized block, will retry: {}", e),
+            }
         }
     }
 
@@ -166,26 +873,52 @@ async fn exex<Node: FullNodeComponents>(
 }
 
 fn main() -> eyre::Result<()> {
-    reth::cli::Cli::parse_args().run(|builder, _| async move {
-        let notifications = broadcast::channel(1000).0;
-        let online_nodes = broadcast::channel(1).0;
+    reth::cli::Cli::<ConfigArgs>::parse().run(|builder, config_args| async move {
+        let config = config_args.load()?;
+        let queues = NodeQueues::new(config.node_queue_size);
+        let registry = NodeRegistry::load(
+            &config.data_dir.join("deregistered.json"),
+            Duration::from_secs(config.node_timeout_secs),
+        )?;
+        let outbox = Arc::new(Mutex::new(Outbox::load(&config.data_dir.join("outbox"))?));
+        let repairs =
+            Arc::new(Mutex::new(RepairQueue::load(&config.data_dir.join("repairs.json"))?));
+        let distributed = Arc::new(Mutex::new(DistributedBlobs::load(
+            &config.data_dir.join("distributed.json"),
+        )?));
+        let filter = BlobFilter::new(config.filter.clone());
+        let coordinator = RepairCoordinator::new(
+            config.repair.clone(),
+            registry.clone(),
+            queues.clone(),
+            outbox.clone(),
+            distributed.clone(),
+            repairs.clone(),
+            config.trusted_setup.clone(),
+        )?;
 
         let server = Server::builder()
             .add_service(RemoteExExServer::new(ExExService {
-                notifications: notifications.clone(),
-                online_nodes: online_nodes.clone(),
+                queues: queues.clone(),
+                registry: registry.clone(),
+                outbox: outbox.clone(),
+                repairs,
+                filter: filter.clone(),
             }))
-            .serve("[::1]:10000".parse().unwrap());
+            .serve(config.grpc_addr);
 
         let handle = builder
             .node(EthereumNode::default())
-            .install_exex("Remote", |ctx| async move { Ok(exex(ctx, notifications)) })
+            .install_exex("Remote", |ctx| async move {
+                Ok(exex(ctx, config, queues, registry, outbox, distributed, filter))
+            })
             .launch()
             .await?;
 
         handle.node.task_executor.spawn_critical("gRPC server", async move {
             server.await.expect("gRPC server crashed")
         });
+        tokio::spawn(coordinator.run());
 
         handle.wait_for_node_exit().await
     })
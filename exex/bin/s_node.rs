@@ -1,8 +1,48 @@
+use alloy::{hex, signers::local::PrivateKeySigner};
+use alloy_eips::eip4844::Bytes48;
 use clap::Parser;
-use exex::proto::{remote_ex_ex_client::RemoteExExClient, NodeOnlineRequest, SubscribeRequest};
+use exex::config::ConfigArgs;
+use exex::dispatch::BlobHeader;
+use exex::kzg::{load_cell_setup, CellSetup};
+use exex::merkle::MerkleProof;
+use exex::outbox::shard_hash;
+use exex::persist::write_private;
+use exex::proto::{
+    notification::Kind,
+    remote_ex_ex_client::RemoteExExClient,
+    storage_node_server::{StorageNode, StorageNodeServer},
+    AckRequest, BlobChunk, CorruptShard, CorruptionReport, GetShardsRequest, HasShardsRequest,
+    HasShardsResponse, HeartbeatRequest, ListBlobsRequest, ListBlobsResponse, NodeOnlineRequest,
+    Notification, RetractionAck, ScrubNowRequest, ScrubNowResponse, Shard, SubscribeRequest,
+};
+use exex::retention::{collect_garbage, RetentionPolicy, Tombstones};
+use exex::scrub::{ScrubReport, Scrubber};
+use exex::sequencer::coding::{scheme_or_default, CodingAlgorithm, CodingScheme};
+use exex::storage::{
+    list_blobs, open_engine, retract_blob_shards, EngineKind, ShardMeta, StorageEngine,
+};
+use exex::time::unix_now_ms;
+use reth::primitives::{Address, B256};
 use reth_tracing::{tracing::info, RethTracer, Tracer};
-use std::path::PathBuf;
-use tokio::time::{sleep, Duration};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{sleep, Duration},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    transport::{Channel, Server},
+    Code, Request, Response, Status,
+};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Wait before subscribing again after the notification stream failed, doubled on every failure
+const MIN_RESUBSCRIBE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESUBSCRIBE_BACKOFF: Duration = Duration::from_secs(30);
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -12,6 +52,153 @@ struct Args {
 
     #[clap(short, long, value_parser, default_value = "storage_node")]
     storage_dir: PathBuf,
+
+    /// How shards are kept in the storage directory
+    #[clap(long, value_enum, default_value_t = EngineKind::Fs)]
+    storage_engine: EngineKind,
+
+    /// Endpoint advertised to the registry for serving shards
+    #[clap(long, default_value = "http://[::1]:50051")]
+    endpoint: String,
+
+    /// Address the shard retrieval service listens on
+    #[clap(long, default_value = "[::1]:50051")]
+    listen_addr: SocketAddr,
+
+    /// Bytes this node is willing to store
+    #[clap(long, default_value_t = 10 * 1024 * 1024 * 1024)]
+    capacity: u64,
+
+    /// Seconds between scrubs of every stored shard, 0 to only scrub when asked to
+    #[clap(long, default_value_t = 3600)]
+    scrub_interval_secs: u64,
+
+    /// Bytes per second the scrubber reads at most, 0 for no limit
+    #[clap(long, default_value_t = 16 * 1024 * 1024)]
+    scrub_rate: u64,
+
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+/// Serves the shards stored on this node back to readers
+#[derive(Debug)]
+struct StorageNodeService {
+    store: Arc<dyn StorageEngine>,
+    /// Blobs deleted by garbage collection, listed so the ExEx does not repair them
+    tombstones: Arc<Mutex<Tombstones>>,
+    /// Asks the scrubber for a pass, which replies with its report
+    scrubs: mpsc::Sender<oneshot::Sender<ScrubReport>>,
+}
+
+#[tonic::async_trait]
+impl StorageNode for StorageNodeService {
+    type GetShardsStream = ReceiverStream<Result<Shard, Status>>;
+
+    async fn get_shards(
+        &self,
+        request: Request<GetShardsRequest>,
+    ) -> Result<Response<Self::GetShardsStream>, Status> {
+        let request = request.into_inner();
+        let indices = if request.indices.is_empty() {
+            self.store.indices(&request.commitment).map_err(internal)?
+        } else {
+            request.indices
+        };
+
+        // Read shards one at a time as the reader consumes them
+        let (tx, rx) = mpsc::channel(16);
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            for index in indices {
+                // The hash is the one the shard was received with, so readers notice it rotting
+                let shard = match read_shard(store.as_ref(), &request.commitment, index) {
+                    Ok(Some((data, meta))) => Ok(Shard {
+                        commitment: request.commitment.clone(),
+                        index,
+                        hash: meta.checksum.to_vec(),
+                        data,
+                        scheme: Some((&meta.scheme).into()),
+                        merkle_root: meta
+                            .merkle
+                            .as_ref()
+                            .map(|m| m.root.to_vec())
+                            .unwrap_or_default(),
+                        merkle_branch: meta
+                            .merkle
+                            .as_ref()
+                            .map(MerkleProof::branch_bytes)
+                            .unwrap_or_default(),
+                        block_number: meta.block_number,
+                        sender: meta.sender.map(|sender| sender.to_vec()).unwrap_or_default(),
+                        block_hash: meta.block_hash.to_vec(),
+                    }),
+                    Ok(None) => continue,
+                    Err(e) => Err(internal(e)),
+                };
+                if tx.send(shard).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn has_shards(
+        &self,
+        request: Request<HasShardsRequest>,
+    ) -> Result<Response<HasShardsResponse>, Status> {
+        let request = request.into_inner();
+        let mut indices = self.store.indices(&request.commitment).map_err(internal)?;
+        if !request.indices.is_empty() {
+            indices.retain(|index| request.indices.contains(index));
+        }
+        Ok(Response::new(HasShardsResponse { indices }))
+    }
+
+    async fn list_blobs(
+        &self,
+        request: Request<ListBlobsRequest>,
+    ) -> Result<Response<ListBlobsResponse>, Status> {
+        let request = request.into_inner();
+        let tombstones = self.tombstones.lock().unwrap();
+        let response = list_blobs(
+            self.store.as_ref(),
+            &tombstones,
+            &request.page_token,
+            request.page_size as usize,
+        )
+        .map_err(internal)?;
+        Ok(Response::new(response))
+    }
+
+    async fn scrub_now(
+        &self,
+        _request: Request<ScrubNowRequest>,
+    ) -> Result<Response<ScrubNowResponse>, Status> {
+        let (reply, report) = oneshot::channel();
+        self.scrubs.send(reply).await.map_err(|_| Status::unavailable("scrubber stopped"))?;
+        let report = report.await.map_err(|_| Status::internal("scrub failed"))?;
+        let corrupted = report
+            .corrupted
+            .iter()
+            .map(|shard| CorruptShard {
+                commitment: shard.commitment.clone(),
+                index: shard.index,
+                reason: shard.reason.to_string(),
+            })
+            .collect();
+        Ok(Response::new(ScrubNowResponse {
+            shards_checked: report.shards_checked,
+            bytes_checked: report.bytes_checked,
+            corrupted,
+        }))
+    }
+}
+
+fn internal(error: std::io::Error) -> Status {
+    Status::internal(error.to_string())
 }
 
 #[tokio::main]
@@ -20,53 +207,421 @@ async fn main() -> eyre::Result<()> {
 
     let args = Args::parse();
     println!("Args: {:?}", args);
+    let config = args.config.load()?;
 
-    let mut client = RemoteExExClient::connect("http://[::1]:10000")
+    let mut client = RemoteExExClient::connect(config.exex_endpoint.clone())
         .await?
         .max_encoding_message_size(usize::MAX)
         .max_decoding_message_size(usize::MAX);
 
-    // Notify the gRPC network that the node is online
-    let online_request = NodeOnlineRequest { node_id: args.node_id };
-    client.notify_online(online_request).await?;
+    let store = open_engine(args.storage_engine, &args.storage_dir, args.node_id)?;
+    info!("Opened {:?} shard store in {}", args.storage_engine, args.storage_dir.display());
+
+    let tombstones =
+        Arc::new(Mutex::new(Tombstones::load(&args.storage_dir.join("expired.json"))?));
 
-    let request = SubscribeRequest { node_id: args.node_id };
+    let (scrubs, scrub_requests) = mpsc::channel(1);
+    let service =
+        StorageNodeService { store: store.clone(), tombstones: tombstones.clone(), scrubs };
+    let server = Server::builder()
+        .add_service(StorageNodeServer::new(service).max_encoding_message_size(usize::MAX))
+        .serve(args.listen_addr);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            eprintln!("Shard retrieval service stopped: {:?}", e);
+        }
+    });
+    info!("Serving shards on {}", args.listen_addr);
+    let node_key = load_or_create_key(&args.storage_dir.join("node.key"))?;
+    let online_request = NodeOnlineRequest {
+        node_id: args.node_id,
+        endpoint: args.endpoint.clone(),
+        capacity: args.capacity,
+        public_key: node_key.credential().verifying_key().to_sec1_bytes().to_vec(),
+    };
 
-    let mut stream = client.subscribe(request).await?.into_inner();
-    info!("Subscription to gRPC stream established");
+    // Register with the gRPC network and keep the registration alive
+    client.notify_online(online_request.clone()).await?;
+    let deregistered = tokio::spawn(heartbeat(client.clone(), online_request, store.clone()));
 
-    std::fs::create_dir_all(&args.storage_dir)?;
-    info!("Created storage directory: {}", args.storage_dir.display());
-    std::fs::create_dir_all(&args.storage_dir)?;
+    let scrubber =
+        Scrubber::new(store.clone(), &args.storage_dir.join("quarantine"), args.scrub_rate);
+    tokio::spawn(scrub(
+        scrubber,
+        client.clone(),
+        args.node_id,
+        Duration::from_secs(args.scrub_interval_secs),
+        scrub_requests,
+    ));
+
+    // Held while writing shards, so garbage collection never deletes one being stored
+    let writes = Arc::new(Mutex::new(()));
+    tokio::spawn(garbage_collection(
+        store.clone(),
+        writes.clone(),
+        tombstones,
+        config.retention.clone(),
+        client.clone(),
+        args.node_id,
+    ));
+
+    let cells = CellVerifier { trusted_setup: config.trusted_setup.clone(), setup: None };
+    tokio::select! {
+        heartbeats = deregistered => {
+            heartbeats?;
+            info!("Node {} was deregistered, stopping", args.node_id);
+            Ok(())
+        }
+        _ = follow_notifications(client, args.node_id, store, writes, cells) => Ok(()),
+    }
+}
 
+/// Store the shards the ExEx sends and delete retracted blobs, subscribing again whenever the
+/// notification stream ends or fails
+async fn follow_notifications(
+    mut client: RemoteExExClient<Channel>,
+    node_id: u32,
+    store: Arc<dyn StorageEngine>,
+    writes: Arc<Mutex<()>>,
+    mut cells: CellVerifier,
+) {
+    let mut backoff = MIN_RESUBSCRIBE_BACKOFF;
     loop {
-        match stream.message().await {
-            Ok(Some(blob_chunk)) => {
-                if blob_chunk.node_id == args.node_id {
-                    info!("Received blob chunk for node {}", args.node_id);
-                    let file_name = args.storage_dir.join(format!(
-                        "chunk_{}_{}_{}.bin",
-                        blob_chunk.name, blob_chunk.node_id, blob_chunk.chunk_index
-                    ));
-                    if let Err(e) = std::fs::write(&file_name, blob_chunk.chunk) {
+        let mut stream = match client.subscribe(SubscribeRequest { node_id }).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => {
+                eprintln!("Failed to subscribe, retrying in {:?}: {:?}", backoff, e);
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RESUBSCRIBE_BACKOFF);
+                continue;
+            }
+        };
+        info!("Subscription to gRPC stream established");
+
+        loop {
+            let message = stream.message().await;
+            if let Ok(Some(_)) = message {
+                backoff = MIN_RESUBSCRIBE_BACKOFF;
+            }
+            match message {
+                Ok(Some(Notification { kind: Some(Kind::BlobChunk(blob_chunk)) })) => {
+                    info!("Received blob chunk for node {}", node_id);
+                    // Checking proofs, writing and syncing the shard all block
+                    let task = (store.clone(), writes.clone(), cells.clone(), blob_chunk.clone());
+                    let stored = tokio::task::spawn_blocking(move || {
+                        let (store, writes, mut cells, blob_chunk) = task;
+                        let stored = store_chunk(store.as_ref(), &writes, &mut cells, &blob_chunk);
+                        (cells, stored)
+                    })
+                    .await;
+                    let stored = match stored {
+                        Ok((verifier, stored)) => {
+                            cells = verifier;
+                            stored
+                        }
+                        Err(e) => Err(e.into()),
+                    };
+                    match stored {
+                        Ok(()) => info!(
+                            "Stored chunk {} of blob {}",
+                            blob_chunk.chunk_index, blob_chunk.commitment
+                        ),
+                        Err(e) => {
+                            // Left unacknowledged, so the ExEx delivers it again
+                            eprintln!(
+                                "Failed to save chunk {} of blob {}. Error: {:?}",
+                                blob_chunk.chunk_index, blob_chunk.commitment, e
+                            );
+                            continue;
+                        }
+                    }
+
+                    let ack = AckRequest {
+                        node_id,
+                        commitment: blob_chunk.commitment,
+                        chunk_index: blob_chunk.chunk_index,
+                        hash: shard_hash(&blob_chunk.chunk).to_vec(),
+                    };
+                    match client.ack(ack).await {
+                        Ok(response) if !response.into_inner().accepted => {
+                            info!("Chunk {} was not accepted by the ExEx", blob_chunk.chunk_index)
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Failed to acknowledge chunk: {:?}", e),
+                    }
+                }
+                Ok(Some(Notification { kind: Some(Kind::RetractBlob(retract_blob)) })) => {
+                    info!(
+                        "Retracting blob {} from orphaned block {}",
+                        retract_blob.commitment, retract_blob.block_hash
+                    );
+                    let Ok(block_hash) = retract_blob.block_hash.parse::<B256>() else {
                         eprintln!(
-                            "Failed to save chunk to file: {}. Error: {:?}",
-                            file_name.display(),
-                            e
+                            "Ignoring retraction with block hash {}",
+                            retract_blob.block_hash
                         );
-                    } else {
-                        info!("Saved chunk to file: {}", file_name.display());
+                        continue;
+                    };
+                    let (task_store, name) = (store.clone(), retract_blob.commitment.clone());
+                    let removed = tokio::task::spawn_blocking(move || {
+                        retract_blob_shards(task_store.as_ref(), &name, block_hash)
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(e.into()));
+                    match removed {
+                        Ok(removed) => {
+                            info!("Removed {} chunks of blob {}", removed, retract_blob.commitment)
+                        }
+                        Err(e) => {
+                            // Left unacknowledged, so the ExEx sends the retraction again
+                            eprintln!(
+                                "Failed to remove chunks of blob {}: {:?}",
+                                retract_blob.commitment, e
+                            );
+                            continue;
+                        }
                     }
+                    let ack = RetractionAck {
+                        node_id,
+                        commitment: retract_blob.commitment,
+                        block_hash: retract_blob.block_hash,
+                    };
+                    if let Err(e) = client.ack_retraction(ack).await {
+                        eprintln!("Failed to acknowledge retraction: {:?}", e);
+                    }
+                }
+                Ok(Some(Notification { kind: None })) => {}
+                Ok(None) => {
+                    info!("Notification stream ended, subscribing again in {:?}", backoff);
+                    break;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Error receiving message, subscribing again in {:?}: {:?}",
+                        backoff, e
+                    );
+                    break;
+                }
+            }
+        }
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RESUBSCRIBE_BACKOFF);
+    }
+}
+
+/// Store a chunk along with its metadata
+///
+/// Chunks are only stored when their Merkle proof holds, and field element shards also when their
+/// cell proof opens the blob commitment.
+fn store_chunk(
+    store: &dyn StorageEngine,
+    writes: &Mutex<()>,
+    cells: &mut CellVerifier,
+    blob_chunk: &BlobChunk,
+) -> eyre::Result<()> {
+    let scheme = scheme_or_default(blob_chunk.scheme.as_ref())?;
+    let index = blob_chunk.chunk_index;
+    if index as usize >= scheme.total_shards() {
+        eyre::bail!("chunk index out of range for {} shards", scheme.total_shards());
+    }
+    let merkle = MerkleProof::from_bytes(&blob_chunk.merkle_root, &blob_chunk.merkle_branch)
+        .ok_or_else(|| eyre::eyre!("rejected shard: missing or malformed Merkle proof"))?;
+    if !merkle.verify(&blob_chunk.chunk, index as usize, scheme.total_shards()) {
+        eyre::bail!("rejected shard: not in the Merkle tree of root {}", merkle.root);
+    }
+    if scheme.algorithm == CodingAlgorithm::FieldElementFft {
+        cells.verify(blob_chunk, &scheme).map_err(|e| eyre::eyre!("rejected shard: {}", e))?;
+    }
+
+    let block_hash = B256::try_from(blob_chunk.block_hash.as_slice())
+        .map_err(|_| eyre::eyre!("rejected shard: block hash must be 32 bytes"))?;
+    let header = BlobHeader {
+        name: &blob_chunk.commitment,
+        scheme,
+        block_number: blob_chunk.block_number,
+        block_hash,
+        sender: Address::try_from(blob_chunk.sender.as_slice()).ok(),
+    };
+    let meta = ShardMeta::new(&header, index, &blob_chunk.chunk, Some(merkle));
+    let _writing = writes.lock().unwrap();
+    Ok(store.put(&meta, &blob_chunk.chunk)?)
+}
+
+/// Read a shard with the metadata it was stored with
+fn read_shard(
+    store: &dyn StorageEngine,
+    name: &str,
+    index: u32,
+) -> std::io::Result<Option<(Vec<u8>, ShardMeta)>> {
+    let Some(meta) = store.meta(name, index)? else { return Ok(None) };
+    Ok(store.get(name, index)?.map(|data| (data, meta)))
+}
+
+/// Checks field element shards against the commitment of their blob
+#[derive(Clone)]
+struct CellVerifier {
+    trusted_setup: Option<PathBuf>,
+    /// Loaded on the first shard that needs it
+    setup: Option<Arc<CellSetup>>,
+}
+
+impl CellVerifier {
+    fn verify(&mut self, blob_chunk: &BlobChunk, scheme: &CodingScheme) -> eyre::Result<()> {
+        let setup = match &self.setup {
+            Some(setup) => setup.clone(),
+            None => {
+                info!("Loading trusted setup to verify cell proofs");
+                self.setup.insert(load_cell_setup(self.trusted_setup.as_deref())?).clone()
+            }
+        };
+        let commitment: Bytes48 = blob_chunk.commitment.parse()?;
+        let proof = Bytes48::try_from(blob_chunk.proof.as_slice())
+            .map_err(|_| eyre::eyre!("missing cell proof"))?;
+        setup.verify_cell(
+            &commitment,
+            blob_chunk.chunk_index as usize,
+            &blob_chunk.chunk,
+            &proof,
+            scheme,
+        )?;
+        Ok(())
+    }
+}
+
+/// Send heartbeats to the registry, registering again if it forgot about this node
+///
+/// Returns once the node is deregistered.
+async fn heartbeat(
+    mut client: RemoteExExClient<Channel>,
+    online_request: NodeOnlineRequest,
+    store: Arc<dyn StorageEngine>,
+) {
+    loop {
+        sleep(HEARTBEAT_INTERVAL).await;
+        let request =
+            HeartbeatRequest { node_id: online_request.node_id, used_bytes: store.used_bytes() };
+        match client.heartbeat(request).await {
+            Ok(response) if !response.into_inner().registered => {
+                info!("Registry lost track of this node, registering again");
+                if let Err(e) = client.notify_online(online_request.clone()).await {
+                    eprintln!("Failed to register: {:?}", e);
                 }
             }
-            Ok(None) => {
-                info!("No more messages in the stream");
+            Ok(_) => {}
+            Err(e) if e.code() == Code::FailedPrecondition => return,
+            Err(e) => eprintln!("Failed to send heartbeat: {:?}", e),
+        }
+    }
+}
+
+/// Scrub the store every `interval` and whenever asked to, reporting corrupted shards for repair
+async fn scrub(
+    scrubber: Scrubber,
+    mut client: RemoteExExClient<Channel>,
+    node_id: u32,
+    interval: Duration,
+    mut requests: mpsc::Receiver<oneshot::Sender<ScrubReport>>,
+) {
+    loop {
+        let reply = tokio::select! {
+            _ = sleep(interval), if !interval.is_zero() => None,
+            request = requests.recv() => match request {
+                Some(reply) => Some(reply),
+                None => return,
+            },
+        };
+
+        info!("Scrubbing stored shards");
+        let report = match scrubber.scrub().await {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Scrub failed: {:?}", e);
+                continue;
+            }
+        };
+        info!(
+            "Scrubbed {} shards ({} bytes), {} corrupted",
+            report.shards_checked,
+            report.bytes_checked,
+            report.corrupted.len()
+        );
+        for shard in &report.corrupted {
+            let request = CorruptionReport {
+                node_id,
+                commitment: shard.commitment.clone(),
+                chunk_index: shard.index,
+                reason: shard.reason.to_string(),
+            };
+            if let Err(e) = client.report_corruption(request).await {
+                eprintln!("Failed to report corrupted shard {}: {:?}", shard.index, e);
+            }
+        }
+        if let Some(reply) = reply {
+            let _ = reply.send(report);
+        }
+    }
+}
+
+/// Delete the blobs the retention policy no longer keeps every interval of the policy
+///
+/// Deleted blobs are kept as tombstones for the policy's `tombstone_secs`. The registry is told
+/// about the freed capacity right away rather than at the next heartbeat.
+async fn garbage_collection(
+    store: Arc<dyn StorageEngine>,
+    writes: Arc<Mutex<()>>,
+    tombstones: Arc<Mutex<Tombstones>>,
+    policy: RetentionPolicy,
+    mut client: RemoteExExClient<Channel>,
+    node_id: u32,
+) {
+    if policy.interval_secs == 0 {
+        return;
+    }
+    let policy = Arc::new(policy);
+    loop {
+        sleep(Duration::from_secs(policy.interval_secs)).await;
+        let (gc_store, gc_writes, gc_policy, gc_tombstones) =
+            (store.clone(), writes.clone(), policy.clone(), tombstones.clone());
+        let report = tokio::task::spawn_blocking(move || {
+            let now_ms = unix_now_ms();
+            let report = collect_garbage(gc_store.as_ref(), &gc_policy, now_ms, &gc_writes)?;
+            let ttl_ms = gc_policy.tombstone_secs.saturating_mul(1000);
+            gc_tombstones.lock().unwrap().update(&report.removed, now_ms, ttl_ms)?;
+            Ok::<_, std::io::Error>(report)
+        })
+        .await;
+        let report = match report {
+            Ok(Ok(report)) => report,
+            Ok(Err(e)) => {
+                eprintln!("Garbage collection failed: {:?}", e);
+                continue;
             }
             Err(e) => {
-                eprintln!("Error receiving message: {:?}", e);
-                sleep(Duration::from_secs(1)).await; // Delay to avoid busy loop on error
+                eprintln!("Garbage collection panicked: {:?}", e);
+                continue;
             }
+        };
+        if report.shards_removed == 0 {
+            continue;
+        }
+        info!(
+            "Collected {} expired blobs ({} shards), freed {} bytes",
+            report.blobs_removed, report.shards_removed, report.bytes_freed
+        );
+        let request = HeartbeatRequest { node_id, used_bytes: store.used_bytes() };
+        if let Err(e) = client.heartbeat(request).await {
+            eprintln!("Failed to report freed capacity: {:?}", e);
         }
-        sleep(Duration::from_millis(100)).await; // Small delay to avoid busy loop
     }
 }
+
+/// Load the node's identity key, generating it on first start
+fn load_or_create_key(path: &Path) -> eyre::Result<PrivateKeySigner> {
+    if path.exists() {
+        return Ok(std::fs::read_to_string(path)?.trim().parse()?);
+    }
+    let signer = PrivateKeySigner::random();
+    write_private(path, hex::encode(signer.to_bytes()).as_bytes())?;
+    info!("Generated node key: {}", path.display());
+    Ok(signer)
+}
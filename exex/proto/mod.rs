@@ -1,3 +0,0 @@
-// @generated
-
-pub mod exex;
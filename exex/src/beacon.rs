@@ -0,0 +1,167 @@
+use crate::blobs::{http_client, status_to_error, SideCarError};
+use reth::primitives::{SealedBlockWithSenders, B256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+const CACHE_CAPACITY: usize = 1024;
+
+/// How blocks are identified when requesting sidecars from the beacon API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockIdKind {
+    /// Beacon block root, as served by Lighthouse/Prysm-compatible APIs
+    #[default]
+    BeaconRoot,
+    /// Execution block hash, as served by the mock consensus layer
+    ExecutionHash,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenesisResponse {
+    data: GenesisData,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenesisData {
+    genesis_time: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpecResponse {
+    data: SpecData,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpecData {
+    #[serde(rename = "SECONDS_PER_SLOT")]
+    seconds_per_slot: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeaderResponse {
+    data: HeaderData,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeaderData {
+    root: B256,
+}
+
+/// Genesis time and slot duration of the beacon chain, to find the slot of a block's timestamp
+#[derive(Debug, Clone, Copy)]
+struct SlotClock {
+    genesis_time: u64,
+    seconds_per_slot: u64,
+}
+
+/// Resolves execution blocks to the root of the beacon block that carried their payload
+///
+/// Roots are learned for free from the `parent_beacon_block_root` of child blocks, and otherwise
+/// looked up by slot through `/eth/v1/beacon/headers`, with the slot duration read from
+/// `/eth/v1/config/spec`. Clones share what they learned.
+#[derive(Debug, Clone)]
+pub struct BeaconBlockResolver {
+    client: reqwest::Client,
+    beacon_url: String,
+    kind: BlockIdKind,
+    cache: Arc<Mutex<RootCache>>,
+}
+
+#[derive(Debug, Default)]
+struct RootCache {
+    clock: Option<SlotClock>,
+    roots: HashMap<B256, B256>,
+    order: VecDeque<B256>,
+}
+
+impl BeaconBlockResolver {
+    pub fn new(beacon_url: &str, kind: BlockIdKind) -> Self {
+        Self {
+            client: http_client(),
+            beacon_url: beacon_url.to_string(),
+            kind,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Record the beacon root of a block's parent, known from the block's own header
+    pub fn observe(&self, block: &SealedBlockWithSenders) {
+        if let Some(root) = block.parent_beacon_block_root {
+            self.insert(block.parent_hash, root);
+        }
+    }
+
+    /// Block id to request the sidecars of `block` with
+    pub async fn resolve(&self, block: &SealedBlockWithSenders) -> Result<B256, SideCarError> {
+        if self.kind == BlockIdKind::ExecutionHash {
+            return Ok(block.hash());
+        }
+        if let Some(root) = self.cache.lock().unwrap().roots.get(&block.hash()) {
+            return Ok(*root);
+        }
+
+        let clock = self.clock().await?;
+        let slot = block.timestamp.saturating_sub(clock.genesis_time) / clock.seconds_per_slot;
+        let header: HeaderResponse = self.get(&format!("/eth/v1/beacon/headers/{}", slot)).await?;
+        println!(
+            "Resolved block {} to beacon root {} at slot {}",
+            block.hash(),
+            header.data.root,
+            slot
+        );
+
+        self.insert(block.hash(), header.data.root);
+        Ok(header.data.root)
+    }
+
+    async fn clock(&self) -> Result<SlotClock, SideCarError> {
+        if let Some(clock) = self.cache.lock().unwrap().clock {
+            return Ok(clock);
+        }
+        let parse = |value: &str| {
+            value.parse::<u64>().map_err(|e| SideCarError::DeserializationError(e.to_string()))
+        };
+        let genesis: GenesisResponse = self.get("/eth/v1/beacon/genesis").await?;
+        let spec: SpecResponse = self.get("/eth/v1/config/spec").await?;
+        let clock = SlotClock {
+            genesis_time: parse(&genesis.data.genesis_time)?,
+            seconds_per_slot: parse(&spec.data.seconds_per_slot)?,
+        };
+        if clock.seconds_per_slot == 0 {
+            return Err(SideCarError::DeserializationError("SECONDS_PER_SLOT is 0".to_string()));
+        }
+        self.cache.lock().unwrap().clock = Some(clock);
+        Ok(clock)
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, SideCarError> {
+        let url = format!("{}{}", self.beacon_url, path);
+        let response = self
+            .client
+            .get(url)
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .map_err(SideCarError::ReqwestError)?;
+        if !response.status().is_success() {
+            return Err(status_to_error(response.status()));
+        }
+        let body = response.text().await.map_err(|e| SideCarError::NetworkError(e.to_string()))?;
+        serde_json::from_str(&body).map_err(|e| SideCarError::DeserializationError(e.to_string()))
+    }
+
+    fn insert(&self, block_hash: B256, root: B256) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.roots.insert(block_hash, root).is_none() {
+            cache.order.push_back(block_hash);
+        }
+        while cache.order.len() > CACHE_CAPACITY {
+            if let Some(evicted) = cache.order.pop_front() {
+                cache.roots.remove(&evicted);
+            }
+        }
+    }
+}
@@ -1,17 +1,17 @@
-use std::{fs::File, io::BufReader};
+use std::{fs::File, io::BufReader, time::Duration};
 
 // Adapted from: https://github.com/paradigmxyz/reth/blob/main/examples/beacon-api-sidecar-fetcher/src/mined_sidecar.rs
+use crate::inclusion::{InclusionError, InclusionVerifier};
 use alloy_rpc_types_beacon::sidecar::{BeaconBlobBundle, BlobData, SidecarIterator};
 use eyre::Result;
 use reqwest::{Error, StatusCode};
 use reth::{
-    primitives::{BlobTransaction, SealedBlockWithSenders, B256},
+    primitives::{kzg::KzgSettings, Address, BlobTransaction, SealedBlockWithSenders, B256},
     transaction_pool::BlobStoreError,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{from_reader, json};
 use thiserror::Error;
-use tokio::time::{sleep, Duration};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockMetadata {
@@ -20,10 +20,18 @@ pub struct BlockMetadata {
     pub gas_used: u64,
 }
 
+impl From<&SealedBlockWithSenders> for BlockMetadata {
+    fn from(block: &SealedBlockWithSenders) -> Self {
+        Self { block_hash: block.hash(), block_number: block.number, gas_used: block.gas_used }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MinedBlob {
     pub transaction: BlobTransaction,
     pub block_metadata: BlockMetadata,
+    /// Address that sent the transaction, `None` if its signature does not recover
+    pub sender: Option<Address>,
 }
 
 #[derive(Debug, Clone)]
@@ -68,22 +76,62 @@ pub enum SideCarError {
     #[error("Data parsing error: {0}")]
     DeserializationError(String),
 
+    #[error("Invalid sidecar for transaction {0}: {1}")]
+    InvalidSidecar(B256, String),
+
+    #[error("Beacon node returned too few sidecars, none left for transaction {0}")]
+    MissingSidecars(B256),
+
+    #[error("Sidecar inclusion check failed: {0}")]
+    InclusionProof(InclusionError),
+
+    #[error("Failed to distribute shards: {0}")]
+    DistributionError(String),
+
     #[error("{0} Error: {1}")]
     UnknownError(u16, String),
 }
 
+impl SideCarError {
+    /// Whether the request may succeed later, e.g. because the sidecars are not published yet
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ReqwestError(_)
+            | Self::BlockNotFound(_)
+            | Self::InternalError(_)
+            | Self::NetworkError(_)
+            | Self::MissingSidecars(_)
+            | Self::DistributionError(_) => true,
+            Self::UnknownError(status, _) => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// Time a beacon API request may take in total, sidecars of a full block are a few megabytes
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Client for beacon API requests, which time out instead of stalling the ExEx
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .expect("TLS backend should initialize")
+}
+
 /// Query the Beacon Layer for missing BlobTransactions
 pub async fn fetch_blobs_for_block(
+    client: &reqwest::Client,
+    beacon_url: &str,
     block_root: B256,
     block: SealedBlockWithSenders,
     txs: Vec<(reth::primitives::TransactionSigned, usize)>,
+    kzg_settings: &KzgSettings,
+    inclusion: &InclusionVerifier,
 ) -> Result<Vec<BlobTransactionEvent>, SideCarError> {
-    const INITIAL_DELAY: Duration = Duration::from_millis(500); // 0.5 second initial delay
-
-    // Initial delay
-    sleep(INITIAL_DELAY).await;
-    let client = reqwest::Client::new();
-    let sidecar_url = format!("http://127.0.0.1:4242/eth/v1/beacon/blob_sidecars/{}", block_root);
+    let sidecar_url = format!("{}/eth/v1/beacon/blob_sidecars/{}", beacon_url, block_root);
     println!("in fetch blobs {:?}", sidecar_url);
     let response = match client.get(sidecar_url).header("Accept", "application/json").send().await {
         Ok(response) => response,
@@ -94,27 +142,7 @@ pub async fn fetch_blobs_for_block(
     };
 
     if !response.status().is_success() {
-        return match response.status() {
-            StatusCode::BAD_REQUEST => {
-                eprintln!("Invalid request to server.");
-                Err(SideCarError::InvalidBlockID("Invalid request to server.".to_string()))
-            }
-            StatusCode::NOT_FOUND => {
-                eprintln!("Requested block not found.");
-                Err(SideCarError::BlockNotFound("Requested block not found.".to_string()))
-            }
-            StatusCode::INTERNAL_SERVER_ERROR => {
-                eprintln!("Server encountered an error.");
-                Err(SideCarError::InternalError("Server encountered an error.".to_string()))
-            }
-            status => {
-                eprintln!("Unhandled HTTP status: {}", status);
-                Err(SideCarError::UnknownError(
-                    status.as_u16(),
-                    "Unhandled HTTP status.".to_string(),
-                ))
-            }
-        };
+        return Err(status_to_error(response.status()));
     }
 
     // read from file
@@ -145,33 +173,86 @@ pub async fn fetch_blobs_for_block(
         }
     };
 
+    // Make sure the sidecars actually belong to the requested block before using them
+    if let Err(e) = inclusion.verify(block_root, &blob_bundle.data) {
+        eprintln!("Sidecars failed inclusion check: {}", e);
+        return Err(SideCarError::InclusionProof(e));
+    }
+
     let mut sidecar_iterator = SidecarIterator::new(blob_bundle);
 
-    let sidecars: Vec<BlobTransactionEvent> = txs
-        .iter()
-        .filter_map(|(tx, blob_len)| {
-            sidecar_iterator.next_sidecar(*blob_len).map(|sidecar| {
-                println!(
-                    "Processing tx with hash: {:?}, sidecar available: {}",
-                    tx.hash(),
-                    sidecar.blobs.len()
-                );
-
-                let transaction = BlobTransaction::try_from_signed(tx.clone(), sidecar)
-                    .expect("should not fail to convert blob tx if it is already eip4844");
-                let block_metadata = BlockMetadata {
-                    block_hash: block.hash(),
-                    block_number: block.number,
-                    gas_used: block.gas_used,
-                };
-                BlobTransactionEvent::Mined(MinedBlob { transaction, block_metadata })
-            })
-        })
-        .collect();
+    let mut sidecars = Vec::new();
+    for (tx, blob_len) in &txs {
+        // Skipping the transaction would finish the block without its blobs
+        let Some(sidecar) = sidecar_iterator.next_sidecar(*blob_len) else {
+            return Err(SideCarError::MissingSidecars(tx.hash()));
+        };
+        println!(
+            "Processing tx with hash: {:?}, sidecar available: {}",
+            tx.hash(),
+            sidecar.blobs.len()
+        );
+
+        // Check every blob against its commitment and proof, and every commitment against the
+        // versioned hashes signed in the transaction
+        let versioned_hashes = tx.blob_versioned_hashes().unwrap_or_default();
+        if let Err(e) = sidecar.validate(&versioned_hashes, kzg_settings) {
+            eprintln!("Invalid sidecar for tx {:?}: {}", tx.hash(), e);
+            return Err(SideCarError::InvalidSidecar(tx.hash(), e.to_string()));
+        }
+
+        let transaction = BlobTransaction::try_from_signed(tx.clone(), sidecar).map_err(|_| {
+            SideCarError::InvalidSidecar(tx.hash(), "not a blob transaction".to_string())
+        })?;
+        let block_metadata = BlockMetadata::from(&block);
+        let sender = tx.recover_signer();
+        sidecars.push(BlobTransactionEvent::Mined(MinedBlob {
+            transaction,
+            block_metadata,
+            sender,
+        }));
+    }
     //println!("CL Response: {:?}", block.block.body);
     Ok(sidecars)
 }
 
+/// Map a non-success beacon API status to a `SideCarError`
+pub(crate) fn status_to_error(status: StatusCode) -> SideCarError {
+    match status {
+        StatusCode::BAD_REQUEST => {
+            eprintln!("Invalid request to server.");
+            SideCarError::InvalidBlockID("Invalid request to server.".to_string())
+        }
+        StatusCode::NOT_FOUND => {
+            eprintln!("Requested block not found.");
+            SideCarError::BlockNotFound("Requested block not found.".to_string())
+        }
+        StatusCode::INTERNAL_SERVER_ERROR => {
+            eprintln!("Server encountered an error.");
+            SideCarError::InternalError("Server encountered an error.".to_string())
+        }
+        status => {
+            eprintln!("Unhandled HTTP status: {}", status);
+            SideCarError::UnknownError(status.as_u16(), "Unhandled HTTP status.".to_string())
+        }
+    }
+}
+
+/// Build `Reorged` events for every blob transaction of a block that is no longer canonical
+pub fn reorged_blobs_for_block(block: &SealedBlockWithSenders) -> Vec<BlobTransactionEvent> {
+    let block_metadata = BlockMetadata::from(block);
+    block
+        .transactions()
+        .filter(|tx| tx.is_eip4844())
+        .map(|tx| {
+            BlobTransactionEvent::Reorged(ReorgedBlob {
+                transaction_hash: tx.hash(),
+                block_metadata: block_metadata.clone(),
+            })
+        })
+        .collect()
+}
+
 // Read from a JSON file
 fn read_blobs_from_file(file_path: &str) -> Result<BeaconBlobBundle, Box<dyn std::error::Error>> {
     let file = File::open(file_path)?;
@@ -0,0 +1,29 @@
+use crate::persist::JsonStore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Last block whose blobs, and those of every block before it, were fully distributed
+#[derive(Debug)]
+pub struct Checkpoint {
+    store: JsonStore<Option<CheckpointFile>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointFile {
+    block_number: u64,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint from `path`, empty if the ExEx never finished a block
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self { store: JsonStore::load(path)? })
+    }
+
+    pub fn block_number(&self) -> Option<u64> {
+        self.store.get().as_ref().map(|checkpoint| checkpoint.block_number)
+    }
+
+    pub fn save(&mut self, block_number: u64) -> std::io::Result<()> {
+        self.store.update(|checkpoint| *checkpoint = Some(CheckpointFile { block_number }))
+    }
+}
@@ -12,8 +12,14 @@ impl From<&ExExNotification> for proto::BlobChunk {
         proto::BlobChunk {
             node_id: notification.node_id,
             chunk_index: notification.chunk_index,
-            chunk: notification.chunk.clone(),
-            name: notification.name.clone(),
+            chunk: notification.chunk.clone().into(),
+            commitment: notification.name.clone(),
+            scheme: None,
+            proof: Vec::new(),
+            merkle_root: Vec::new(),
+            merkle_branch: Vec::new(),
+            block_number: 0,
+            sender: Vec::new(),
         }
     }
 }
@@ -23,8 +29,8 @@ impl From<proto::BlobChunk> for ExExNotification {
         ExExNotification {
             node_id: blob_chunk.node_id,
             chunk_index: blob_chunk.chunk_index,
-            chunk: blob_chunk.chunk,
-            name: blob_chunk.name,
+            chunk: blob_chunk.chunk.to_vec(),
+            name: blob_chunk.commitment,
         }
     }
 }
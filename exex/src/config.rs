@@ -0,0 +1,172 @@
+use crate::{
+    beacon::BlockIdKind,
+    filter::FilterPolicy,
+    repair::RepairPolicy,
+    retention::RetentionPolicy,
+    retry::RetryPolicy,
+    sequencer::coding::{CodingError, CodingScheme},
+};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, path::PathBuf};
+
+/// Settings shared by every blobster binary
+///
+/// Values are resolved in order of precedence: CLI flags, then environment variables, then the
+/// optional TOML file, then the defaults below.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlobsterConfig {
+    /// Base URL of the beacon node API serving blob sidecars
+    pub beacon_url: String,
+    /// How blocks are identified when requesting sidecars
+    pub block_id: BlockIdKind,
+    /// KZG trusted setup file, the mainnet setup is used when unset
+    pub trusted_setup: Option<PathBuf>,
+    /// JSON validator set to check block header signatures against, unchecked when unset
+    pub validator_set: Option<PathBuf>,
+    /// Which blob transactions are stored, all of them by default
+    pub filter: FilterPolicy,
+    /// Retry policy for blocks whose sidecars are not available yet
+    pub retry: RetryPolicy,
+    /// How often storage nodes are audited for blobs that lost shards
+    pub repair: RepairPolicy,
+    /// Which blobs storage nodes keep and how often they delete the rest
+    pub retention: RetentionPolicy,
+    /// Erasure coding scheme new blobs are encoded with
+    pub coding: CodingScheme,
+    /// Directory holding the ExEx's persistent state
+    pub data_dir: PathBuf,
+    /// Block to backfill from on startup, defaults to the one after the checkpoint
+    pub backfill_from: Option<u64>,
+    /// Seconds without a heartbeat after which a storage node is no longer live
+    pub node_timeout_secs: u64,
+    /// Notifications buffered per storage node before deliveries to it fail
+    pub node_queue_size: usize,
+    /// Seconds a storage node has to acknowledge a shard before it is delivered again
+    pub ack_timeout_secs: u64,
+    /// Address the ExEx gRPC server listens on
+    pub grpc_addr: SocketAddr,
+    /// Endpoint clients use to reach the ExEx gRPC server
+    pub exex_endpoint: String,
+}
+
+impl Default for BlobsterConfig {
+    fn default() -> Self {
+        Self {
+            beacon_url: "http://127.0.0.1:4242".to_string(),
+            block_id: BlockIdKind::default(),
+            trusted_setup: None,
+            validator_set: None,
+            filter: FilterPolicy::default(),
+            retry: RetryPolicy::default(),
+            repair: RepairPolicy::default(),
+            retention: RetentionPolicy::default(),
+            coding: CodingScheme::default(),
+            data_dir: PathBuf::from("blobster_data"),
+            backfill_from: None,
+            node_timeout_secs: 30,
+            node_queue_size: 1024,
+            ack_timeout_secs: 30,
+            grpc_addr: "[::1]:10000".parse().unwrap(),
+            exex_endpoint: "http://[::1]:10000".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    #[error("Failed to parse config file {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+
+    #[error("Invalid coding scheme: {0}")]
+    Coding(#[from] CodingError),
+}
+
+/// CLI flags (and their environment variables) overriding the config file
+///
+/// Flags are prefixed with `blobster.` so they do not clash with reth's own under `exex node`.
+#[derive(Debug, Clone, Default, Args)]
+#[command(next_help_heading = "Blobster")]
+pub struct ConfigArgs {
+    /// Path to a TOML config file
+    #[arg(long = "blobster.config", env = "BLOBSTER_CONFIG")]
+    pub config_path: Option<PathBuf>,
+
+    /// Base URL of the beacon node API
+    #[arg(long = "blobster.beacon-url", env = "BLOBSTER_BEACON_URL")]
+    pub beacon_url: Option<String>,
+
+    /// How blocks are identified when requesting sidecars
+    #[arg(long = "blobster.block-id", env = "BLOBSTER_BLOCK_ID", value_enum)]
+    pub block_id: Option<BlockIdKind>,
+
+    /// KZG trusted setup file used to verify sidecars
+    #[arg(long = "blobster.trusted-setup", env = "BLOBSTER_TRUSTED_SETUP")]
+    pub trusted_setup: Option<PathBuf>,
+
+    /// Validator set file used to verify block header signatures
+    #[arg(long = "blobster.validator-set", env = "BLOBSTER_VALIDATOR_SET")]
+    pub validator_set: Option<PathBuf>,
+
+    /// Directory holding the ExEx's persistent state
+    #[arg(long = "blobster.data-dir", env = "BLOBSTER_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Block to backfill from on startup
+    #[arg(long = "blobster.backfill-from", env = "BLOBSTER_BACKFILL_FROM")]
+    pub backfill_from: Option<u64>,
+
+    /// Address the ExEx gRPC server listens on
+    #[arg(long = "blobster.grpc-addr", env = "BLOBSTER_GRPC_ADDR")]
+    pub grpc_addr: Option<SocketAddr>,
+
+    /// Endpoint of the ExEx gRPC server
+    #[arg(long = "blobster.exex-endpoint", env = "BLOBSTER_EXEX_ENDPOINT")]
+    pub exex_endpoint: Option<String>,
+}
+
+impl ConfigArgs {
+    /// Load the config file, if any, and apply the CLI and environment overrides on top
+    pub fn load(&self) -> Result<BlobsterConfig, ConfigError> {
+        let mut config = match &self.config_path {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|e| ConfigError::Read(path.clone(), e))?;
+                toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.clone(), e))?
+            }
+            None => BlobsterConfig::default(),
+        };
+
+        if let Some(beacon_url) = &self.beacon_url {
+            config.beacon_url = beacon_url.clone();
+        }
+        if let Some(block_id) = self.block_id {
+            config.block_id = block_id;
+        }
+        if let Some(trusted_setup) = &self.trusted_setup {
+            config.trusted_setup = Some(trusted_setup.clone());
+        }
+        if let Some(validator_set) = &self.validator_set {
+            config.validator_set = Some(validator_set.clone());
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.data_dir = data_dir.clone();
+        }
+        if let Some(backfill_from) = self.backfill_from {
+            config.backfill_from = Some(backfill_from);
+        }
+        if let Some(grpc_addr) = self.grpc_addr {
+            config.grpc_addr = grpc_addr;
+        }
+        if let Some(exex_endpoint) = &self.exex_endpoint {
+            config.exex_endpoint = exex_endpoint.clone();
+        }
+
+        config.coding.validate()?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    merkle::MerkleProof,
+    proto::{notification::Kind, BlobChunk, Notification, RetractBlob},
+    sequencer::coding::CodingScheme,
+};
+use alloy_eips::eip4844::Bytes48;
+use bytes::Bytes;
+use reth::primitives::{Address, B256};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+pub type NotificationStream = ReceiverStream<Result<Notification, Status>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DispatchError {
+    #[error("Storage node {0} is not subscribed")]
+    NotSubscribed(u32),
+    #[error("Queue of storage node {0} is full")]
+    QueueFull(u32),
+}
+
+/// Bounded notification queue per subscribed storage node
+#[derive(Debug, Clone)]
+pub struct NodeQueues {
+    queues: Arc<RwLock<HashMap<u32, mpsc::Sender<Result<Notification, Status>>>>>,
+    queue_size: usize,
+}
+
+impl NodeQueues {
+    pub fn new(queue_size: usize) -> Self {
+        Self { queues: Arc::default(), queue_size }
+    }
+
+    /// Open the stream of a node, replacing the one of an earlier subscription
+    pub fn subscribe(&self, node_id: u32) -> NotificationStream {
+        let (tx, rx) = mpsc::channel(self.queue_size);
+        self.queues.write().unwrap().insert(node_id, tx);
+        ReceiverStream::new(rx)
+    }
+
+    /// Queue a notification for one node without waiting, so a slow node never holds up others
+    pub fn send(&self, node_id: u32, notification: Notification) -> Result<(), DispatchError> {
+        let result = match self.queues.read().unwrap().get(&node_id) {
+            Some(queue) => queue.try_send(Ok(notification)),
+            None => return Err(DispatchError::NotSubscribed(node_id)),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(DispatchError::QueueFull(node_id)),
+            Err(TrySendError::Closed(_)) => {
+                self.remove_closed();
+                Err(DispatchError::NotSubscribed(node_id))
+            }
+        }
+    }
+
+    /// Queue the shards of a blob for their nodes, failed deliveries are left to the outbox to
+    /// retry
+    pub fn send_chunks(&self, name: &str, chunks: impl IntoIterator<Item = BlobChunk>) {
+        let mut failed = 0;
+        let mut failed_nodes = BTreeSet::new();
+        for blob_chunk in chunks {
+            let node_id = blob_chunk.node_id;
+            let notification = Notification { kind: Some(Kind::BlobChunk(blob_chunk)) };
+            // Fails when the node is not subscribed or too far behind
+            if self.send(node_id, notification).is_err() {
+                failed += 1;
+                failed_nodes.insert(node_id);
+            }
+        }
+        if failed > 0 {
+            eprintln!(
+                "Failed to send {} shards of blob {} to nodes {:?}, will redeliver",
+                failed, name, failed_nodes
+            );
+        }
+    }
+
+    /// Queue the retraction of a blob from an orphaned block for a node, failed deliveries are
+    /// left to the outbox to retry
+    pub fn send_retraction(&self, node_id: u32, name: &str, block_hash: B256) {
+        let retract_blob =
+            RetractBlob { commitment: name.to_string(), block_hash: block_hash.to_string() };
+        let notification = Notification { kind: Some(Kind::RetractBlob(retract_blob)) };
+        if let Err(e) = self.send(node_id, notification) {
+            eprintln!("Failed to send retraction of blob {}, will redeliver: {}", name, e);
+        }
+    }
+
+    /// Ids of the nodes with an open stream
+    pub fn subscribed(&self) -> Vec<u32> {
+        self.queues.read().unwrap().keys().copied().collect()
+    }
+
+    // Forget streams whose node disconnected
+    fn remove_closed(&self) {
+        self.queues.write().unwrap().retain(|_, queue| !queue.is_closed());
+    }
+}
+
+/// The blob a chunk belongs to, as told to storage nodes
+#[derive(Debug, Clone, Copy)]
+pub struct BlobHeader<'a> {
+    pub name: &'a str,
+    pub scheme: CodingScheme,
+    pub block_number: u64,
+    pub block_hash: B256,
+    /// Address that sent the blob transaction, if it could be recovered
+    pub sender: Option<Address>,
+}
+
+/// Shard of a blob for a storage node, with the proofs it is checked by
+pub fn blob_chunk(
+    blob: &BlobHeader<'_>,
+    chunk_index: u32,
+    node_id: u32,
+    chunk: Bytes,
+    proof: Option<Bytes48>,
+    merkle: Option<&MerkleProof>,
+) -> BlobChunk {
+    BlobChunk {
+        node_id,
+        chunk_index,
+        chunk,
+        commitment: blob.name.to_string(),
+        scheme: Some((&blob.scheme).into()),
+        proof: proof.map(|proof| proof.to_vec()).unwrap_or_default(),
+        merkle_root: merkle.map(|merkle| merkle.root.to_vec()).unwrap_or_default(),
+        merkle_branch: merkle.map(MerkleProof::branch_bytes).unwrap_or_default(),
+        block_number: blob.block_number,
+        sender: blob.sender.map(|sender| sender.to_vec()).unwrap_or_default(),
+        block_hash: blob.block_hash.to_vec(),
+    }
+}
@@ -0,0 +1,136 @@
+use crate::persist::JsonStore;
+use reth::primitives::B256;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// Commitments distributed per blob transaction, by block, so the shards of orphaned blocks can be
+/// retracted, also after a restart
+///
+/// Blocks are forgotten once finalized, as they can no longer be reorged out. The blobs of orphaned
+/// blocks are remembered, so shards a node kept of them are never taken for canonical ones.
+#[derive(Debug)]
+pub struct DistributedBlobs {
+    record: JsonStore<Record>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Record {
+    /// Highest block forgotten as finalized
+    finalized: Option<u64>,
+    blocks: BTreeMap<u64, BTreeMap<B256, Vec<String>>>,
+    /// Commitments retracted by orphaned block hash
+    retracted: BTreeMap<B256, Vec<String>>,
+}
+
+impl DistributedBlobs {
+    /// Load the record from `path`, starting empty if the file does not exist yet
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self { record: JsonStore::load(path)? })
+    }
+
+    /// Record the commitments of the blobs of a transaction that were sent to storage nodes
+    pub fn add(
+        &mut self,
+        block_number: u64,
+        transaction_hash: B256,
+        names: Vec<String>,
+    ) -> std::io::Result<()> {
+        self.record.update(|record| {
+            record.blocks.entry(block_number).or_default().insert(transaction_hash, names);
+        })
+    }
+
+    /// Commitments of the blobs of a transaction, `None` if none were sent
+    pub fn get(&self, block_number: u64, transaction_hash: B256) -> Option<Vec<String>> {
+        self.record.get().blocks.get(&block_number)?.get(&transaction_hash).cloned()
+    }
+
+    /// Forget the blobs of a transaction orphaned with block `block_hash`, returning their
+    /// commitments
+    pub fn take(
+        &mut self,
+        block_number: u64,
+        block_hash: B256,
+        transaction_hash: B256,
+    ) -> std::io::Result<Option<Vec<String>>> {
+        let recorded = self
+            .record
+            .get()
+            .blocks
+            .get(&block_number)
+            .is_some_and(|transactions| transactions.contains_key(&transaction_hash));
+        if !recorded {
+            return Ok(None);
+        }
+        self.record.update(|record| {
+            let transactions = record.blocks.get_mut(&block_number)?;
+            let names = transactions.remove(&transaction_hash);
+            if transactions.is_empty() {
+                record.blocks.remove(&block_number);
+            }
+            record.retracted.entry(block_hash).or_default().extend(names.iter().flatten().cloned());
+            names
+        })
+    }
+
+    /// Whether a blob included in block `block_hash` at `block_number` belongs to the canonical
+    /// chain, i.e. is still recorded or its block was finalized since, and was not retracted
+    pub fn is_canonical(&self, name: &str, block_number: u64, block_hash: B256) -> bool {
+        let record = self.record.get();
+        let retracted = record
+            .retracted
+            .get(&block_hash)
+            .is_some_and(|names| names.iter().any(|retracted| retracted == name));
+        let recorded = record.blocks.get(&block_number).is_some_and(|transactions| {
+            transactions.values().flatten().any(|recorded| recorded == name)
+        });
+        !retracted
+            && (recorded || record.finalized.is_some_and(|finalized| block_number <= finalized))
+    }
+
+    /// Forget every block up to and including `block_number`
+    pub fn prune(&mut self, block_number: u64) -> std::io::Result<()> {
+        if self.record.get().finalized.is_some_and(|finalized| finalized >= block_number) {
+            return Ok(());
+        }
+        self.record.update(|record| {
+            record.blocks = record.blocks.split_off(&(block_number + 1));
+            record.finalized = Some(block_number);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_canonical_blobs_from_orphaned_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("distributed.json");
+        let mut distributed = DistributedBlobs::load(&path).unwrap();
+        let (kept, orphaned) = (B256::repeat_byte(1), B256::repeat_byte(2));
+        let (block, orphaned_block) = (B256::repeat_byte(3), B256::repeat_byte(4));
+        distributed.add(10, kept, vec!["a".into()]).unwrap();
+        distributed.add(11, orphaned, vec!["b".into()]).unwrap();
+        distributed.add(12, kept, vec!["c".into()]).unwrap();
+        assert!(!distributed.is_canonical("a", 11, block));
+
+        let taken = distributed.take(11, orphaned_block, orphaned).unwrap();
+        assert_eq!(taken, Some(vec!["b".to_string()]));
+        assert!(!distributed.is_canonical("b", 11, orphaned_block));
+        assert!(distributed.is_canonical("a", 10, block));
+
+        distributed.prune(11).unwrap();
+        drop(distributed);
+        let distributed = DistributedBlobs::load(&path).unwrap();
+        assert_eq!(distributed.get(10, kept), None);
+        assert!(distributed.is_canonical("a", 10, block));
+        assert!(distributed.is_canonical("c", 12, block));
+        // Finalized, but in the block that was orphaned
+        assert!(!distributed.is_canonical("b", 11, orphaned_block));
+        assert!(distributed.is_canonical("b", 11, block));
+        assert!(!distributed.is_canonical("b", 12, block));
+        assert!(!distributed.is_canonical("d", 13, block));
+    }
+}
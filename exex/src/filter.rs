@@ -0,0 +1,239 @@
+use reth::primitives::{Address, FixedBytes, SealedBlockWithSenders, TransactionSigned};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Which blob transactions the ExEx stores, empty lists let every transaction through
+///
+/// A transaction is stored when no deny list matches it and every non-empty allow list does.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterPolicy {
+    /// Senders whose blobs are stored, any sender when empty
+    pub allow_from: Vec<Address>,
+    /// Senders whose blobs are never stored
+    pub deny_from: Vec<Address>,
+    /// Recipients, e.g. rollup inboxes, whose blobs are stored, any recipient when empty
+    pub allow_to: Vec<Address>,
+    /// Recipients whose blobs are never stored
+    pub deny_to: Vec<Address>,
+    /// Calldata selectors of transactions whose blobs are stored, any call when empty
+    pub allow_selectors: Vec<FixedBytes<4>>,
+    /// Calldata selectors of transactions whose blobs are never stored
+    pub deny_selectors: Vec<FixedBytes<4>>,
+}
+
+impl FilterPolicy {
+    /// The first list that rejects a transaction, deny lists before allow lists
+    pub fn reject(&self, from: Address, tx: &TransactionSigned) -> Option<Filter> {
+        self.reject_call(from, tx.to(), tx.input())
+    }
+
+    /// The first list that rejects a call from `from` to `to` with calldata `input`
+    fn reject_call(&self, from: Address, to: Option<Address>, input: &[u8]) -> Option<Filter> {
+        let selector = input.get(..4).map(FixedBytes::<4>::from_slice);
+        let listed = |list: &[Address], address: Option<Address>| {
+            address.is_some_and(|address| list.contains(&address))
+        };
+        let selected = |list: &[FixedBytes<4>]| selector.is_some_and(|s| list.contains(&s));
+
+        if self.deny_from.contains(&from) {
+            Some(Filter::DenyFrom)
+        } else if listed(&self.deny_to, to) {
+            Some(Filter::DenyTo)
+        } else if selected(&self.deny_selectors) {
+            Some(Filter::DenySelector)
+        } else if !self.allow_from.is_empty() && !self.allow_from.contains(&from) {
+            Some(Filter::AllowFrom)
+        } else if !self.allow_to.is_empty() && !listed(&self.allow_to, to) {
+            Some(Filter::AllowTo)
+        } else if !self.allow_selectors.is_empty() && !selected(&self.allow_selectors) {
+            Some(Filter::AllowSelector)
+        } else {
+            None
+        }
+    }
+}
+
+/// One list of a filter policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Filter {
+    AllowFrom,
+    DenyFrom,
+    AllowTo,
+    DenyTo,
+    AllowSelector,
+    DenySelector,
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::AllowFrom => "allow_from",
+            Self::DenyFrom => "deny_from",
+            Self::AllowTo => "allow_to",
+            Self::DenyTo => "deny_to",
+            Self::AllowSelector => "allow_selectors",
+            Self::DenySelector => "deny_selectors",
+        })
+    }
+}
+
+/// Blob transactions of the blocks committed since the ExEx started, by how the filter took them
+///
+/// Transactions are counted once per committed block, however many times its sidecars are fetched.
+#[derive(Debug, Clone, Default)]
+pub struct FilterStats {
+    /// Transactions that passed every list
+    pub passed: u64,
+    /// Blobs of the transactions that passed
+    pub passed_blobs: u64,
+    /// Transactions rejected, by the list that rejected them
+    pub rejected: BTreeMap<Filter, u64>,
+}
+
+impl FilterStats {
+    /// Count a transaction with `blobs` blobs, rejected by `rejected` if any
+    fn record(&mut self, rejected: Option<Filter>, blobs: usize) {
+        match rejected {
+            Some(filter) => *self.rejected.entry(filter).or_default() += 1,
+            None => {
+                self.passed += 1;
+                self.passed_blobs += blobs as u64;
+            }
+        }
+    }
+}
+
+/// Filter policy along with its statistics, shared with the gRPC service reporting them
+#[derive(Debug, Clone)]
+pub struct BlobFilter {
+    policy: Arc<FilterPolicy>,
+    stats: Arc<Mutex<FilterStats>>,
+}
+
+impl BlobFilter {
+    pub fn new(policy: FilterPolicy) -> Self {
+        Self { policy: Arc::new(policy), stats: Arc::default() }
+    }
+
+    /// Whether the blobs of a transaction sent by `from` should be stored
+    pub fn admit(&self, from: Address, tx: &TransactionSigned) -> bool {
+        self.policy.reject(from, tx).is_none()
+    }
+
+    /// Count the blob transactions of a block just committed
+    pub fn count(&self, block: &SealedBlockWithSenders) {
+        let mut stats = self.stats.lock().unwrap();
+        for (from, tx) in block.transactions_with_sender() {
+            if let Some(hashes) = tx.blob_versioned_hashes() {
+                stats.record(self.policy.reject(*from, tx), hashes.len());
+            }
+        }
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECTOR: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+    fn address(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn call(selector: [u8; 4]) -> Vec<u8> {
+        selector.into_iter().chain([0; 32]).collect()
+    }
+
+    #[test]
+    fn lets_everything_through_by_default() {
+        let policy = FilterPolicy::default();
+        assert_eq!(policy.reject_call(address(1), Some(address(2)), &call(SELECTOR)), None);
+        assert_eq!(policy.reject_call(address(1), None, &[]), None);
+    }
+
+    #[test]
+    fn applies_the_sender_lists() {
+        let policy = FilterPolicy { deny_from: vec![address(1)], ..Default::default() };
+        assert_eq!(policy.reject_call(address(1), None, &[]), Some(Filter::DenyFrom));
+        assert_eq!(policy.reject_call(address(2), None, &[]), None);
+
+        let policy = FilterPolicy { allow_from: vec![address(1)], ..Default::default() };
+        assert_eq!(policy.reject_call(address(1), None, &[]), None);
+        assert_eq!(policy.reject_call(address(2), None, &[]), Some(Filter::AllowFrom));
+    }
+
+    #[test]
+    fn applies_the_recipient_lists() {
+        let policy = FilterPolicy { deny_to: vec![address(2)], ..Default::default() };
+        assert_eq!(policy.reject_call(address(1), Some(address(2)), &[]), Some(Filter::DenyTo));
+        assert_eq!(policy.reject_call(address(1), Some(address(3)), &[]), None);
+        assert_eq!(policy.reject_call(address(1), None, &[]), None);
+
+        let policy = FilterPolicy { allow_to: vec![address(2)], ..Default::default() };
+        assert_eq!(policy.reject_call(address(1), Some(address(2)), &[]), None);
+        assert_eq!(policy.reject_call(address(1), Some(address(3)), &[]), Some(Filter::AllowTo));
+        // A transaction without a recipient is on no allow list
+        assert_eq!(policy.reject_call(address(1), None, &[]), Some(Filter::AllowTo));
+    }
+
+    #[test]
+    fn applies_the_selector_lists() {
+        let other = [0x12, 0x34, 0x56, 0x78];
+        let policy = FilterPolicy { deny_selectors: vec![SELECTOR.into()], ..Default::default() };
+        assert_eq!(
+            policy.reject_call(address(1), None, &call(SELECTOR)),
+            Some(Filter::DenySelector)
+        );
+        assert_eq!(policy.reject_call(address(1), None, &call(other)), None);
+        assert_eq!(policy.reject_call(address(1), None, &SELECTOR[..3]), None);
+
+        let policy = FilterPolicy { allow_selectors: vec![SELECTOR.into()], ..Default::default() };
+        assert_eq!(policy.reject_call(address(1), None, &call(SELECTOR)), None);
+        assert_eq!(policy.reject_call(address(1), None, &call(other)), Some(Filter::AllowSelector));
+        // Calldata too short for a selector matches no allow list
+        assert_eq!(
+            policy.reject_call(address(1), None, &SELECTOR[..3]),
+            Some(Filter::AllowSelector)
+        );
+    }
+
+    #[test]
+    fn checks_deny_lists_before_allow_lists() {
+        let policy = FilterPolicy {
+            allow_from: vec![address(1)],
+            deny_from: vec![address(1)],
+            deny_to: vec![address(2)],
+            deny_selectors: vec![SELECTOR.into()],
+            ..Default::default()
+        };
+        let rejected = policy.reject_call(address(1), Some(address(2)), &call(SELECTOR));
+        assert_eq!(rejected, Some(Filter::DenyFrom));
+        let rejected = policy.reject_call(address(3), Some(address(2)), &call(SELECTOR));
+        assert_eq!(rejected, Some(Filter::DenyTo));
+        let rejected = policy.reject_call(address(3), None, &call(SELECTOR));
+        assert_eq!(rejected, Some(Filter::DenySelector));
+        assert_eq!(policy.reject_call(address(3), None, &[]), Some(Filter::AllowFrom));
+    }
+
+    #[test]
+    fn counts_transactions_by_outcome() {
+        let mut stats = FilterStats::default();
+        stats.record(None, 2);
+        stats.record(None, 3);
+        stats.record(Some(Filter::DenyTo), 1);
+        stats.record(Some(Filter::DenyTo), 6);
+        stats.record(Some(Filter::AllowFrom), 1);
+        assert_eq!((stats.passed, stats.passed_blobs), (2, 5));
+        let rejected = BTreeMap::from([(Filter::AllowFrom, 1), (Filter::DenyTo, 2)]);
+        assert_eq!(stats.rejected, rejected);
+    }
+}
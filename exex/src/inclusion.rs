@@ -0,0 +1,323 @@
+use crate::merkle::{hash_pair, is_valid_merkle_branch};
+use alloy_rpc_types_beacon::{header::Header, sidecar::BlobData};
+use blst::{
+    min_pk::{PublicKey, Signature},
+    BLST_ERROR,
+};
+use reth::primitives::{Bytes, FixedBytes, B256};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+use thiserror::Error;
+
+/// Depth of the branch from a commitment to the block body root (Deneb)
+pub const KZG_COMMITMENT_INCLUSION_PROOF_DEPTH: usize = 17;
+/// Position of `blob_kzg_commitments` in the `BeaconBlockBody` container
+const BLOB_KZG_COMMITMENTS_INDEX: u64 = 11;
+/// Depth of the `List[KZGCommitment, MAX_BLOB_COMMITMENTS_PER_BLOCK]` data tree
+const BLOB_COMMITMENTS_LIST_DEPTH: u64 = 12;
+const DOMAIN_BEACON_PROPOSER: [u8; 4] = [0, 0, 0, 0];
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+#[derive(Debug, Error)]
+pub enum InclusionError {
+    #[error("Blob {0} has an inclusion proof of length {1}")]
+    ProofLength(u64, usize),
+
+    #[error("Blob index {0} is beyond the commitment list")]
+    IndexOutOfRange(u64),
+
+    #[error("Commitment of blob {0} is not included in body root {1}")]
+    NotIncluded(u64, B256),
+
+    #[error("Sidecars reference different block headers")]
+    HeaderMismatch,
+
+    #[error("Header root {0} does not match the requested block {1}")]
+    WrongBlock(B256, B256),
+
+    #[error("Proposer {0} is not in the validator set")]
+    UnknownProposer(u64),
+
+    #[error("Invalid signature from proposer {0}")]
+    InvalidSignature(u64),
+
+    #[error("Failed to load validator set: {0}")]
+    ValidatorSet(String),
+}
+
+/// Proposer keys and signing domain used to check block header signatures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatorSet {
+    pub genesis_validators_root: B256,
+    pub fork_version: FixedBytes<4>,
+    /// Public keys by validator index
+    pub validators: HashMap<u64, Bytes>,
+}
+
+impl ValidatorSet {
+    /// Read a validator set from a JSON file
+    pub fn load(path: &Path) -> Result<Self, InclusionError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| InclusionError::ValidatorSet(format!("{}: {}", path.display(), e)))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| InclusionError::ValidatorSet(format!("{}: {}", path.display(), e)))
+    }
+
+    fn domain(&self) -> B256 {
+        let fork_data_root =
+            hash_pair(&pad_chunk(self.fork_version.as_slice()), &self.genesis_validators_root.0);
+        let mut domain = [0u8; 32];
+        domain[..4].copy_from_slice(&DOMAIN_BEACON_PROPOSER);
+        domain[4..].copy_from_slice(&fork_data_root[..28]);
+        B256::from(domain)
+    }
+
+    /// Root the proposer signs a header under, as in the spec's `compute_signing_root`
+    fn signing_root(&self, header: &Header) -> B256 {
+        B256::from(hash_pair(&header_root(header).0, &self.domain().0))
+    }
+
+    fn verify_header(&self, header: &Header) -> Result<(), InclusionError> {
+        let proposer_index = header.message.proposer_index;
+        let public_key = self
+            .validators
+            .get(&proposer_index)
+            .and_then(|key| PublicKey::from_bytes(key).ok())
+            .ok_or(InclusionError::UnknownProposer(proposer_index))?;
+        let signature = Signature::from_bytes(&header.signature)
+            .map_err(|_| InclusionError::InvalidSignature(proposer_index))?;
+
+        let signing_root = self.signing_root(header);
+        match signature.verify(true, signing_root.as_slice(), BLS_DST, &[], &public_key, true) {
+            BLST_ERROR::BLST_SUCCESS => Ok(()),
+            _ => Err(InclusionError::InvalidSignature(proposer_index)),
+        }
+    }
+}
+
+/// Checks that sidecars belong to the block they were requested for
+#[derive(Debug, Clone, Default)]
+pub struct InclusionVerifier {
+    /// Whether the requested block id is a beacon block root the header must hash to
+    pub check_block_root: bool,
+    /// Validator set to check header signatures against, if any
+    pub validators: Option<ValidatorSet>,
+}
+
+impl InclusionVerifier {
+    /// Verify every sidecar's commitment against the body root of its signed block header
+    pub fn verify(&self, block_root: B256, sidecars: &[BlobData]) -> Result<(), InclusionError> {
+        let Some(first) = sidecars.first() else {
+            return Ok(());
+        };
+        let header = &first.signed_block_header;
+
+        for sidecar in sidecars {
+            if sidecar.signed_block_header != *header {
+                return Err(InclusionError::HeaderMismatch);
+            }
+            verify_commitment_inclusion(sidecar)?;
+        }
+
+        if self.check_block_root {
+            let root = header_root(header);
+            if root != block_root {
+                return Err(InclusionError::WrongBlock(root, block_root));
+            }
+        }
+        if let Some(validators) = &self.validators {
+            validators.verify_header(header)?;
+        }
+        Ok(())
+    }
+}
+
+/// Verify `kzg_commitment_inclusion_proof` as in the Deneb `verify_blob_sidecar_inclusion_proof`
+pub fn verify_commitment_inclusion(sidecar: &BlobData) -> Result<(), InclusionError> {
+    let proof = &sidecar.kzg_commitment_inclusion_proof;
+    if proof.len() != KZG_COMMITMENT_INCLUSION_PROOF_DEPTH {
+        return Err(InclusionError::ProofLength(sidecar.index, proof.len()));
+    }
+    // A larger index would spill into the bits selecting the body field
+    if sidecar.index >= 1 << BLOB_COMMITMENTS_LIST_DEPTH {
+        return Err(InclusionError::IndexOutOfRange(sidecar.index));
+    }
+
+    let commitment = sidecar.kzg_commitment.as_slice();
+    let leaf = hash_pair(&pad_chunk(&commitment[..32]), &pad_chunk(&commitment[32..]));
+    let index = (BLOB_KZG_COMMITMENTS_INDEX << (BLOB_COMMITMENTS_LIST_DEPTH + 1)) | sidecar.index;
+
+    let body_root = sidecar.signed_block_header.message.body_root;
+    if is_valid_merkle_branch(leaf, proof, index, body_root) {
+        Ok(())
+    } else {
+        Err(InclusionError::NotIncluded(sidecar.index, body_root))
+    }
+}
+
+/// SSZ `hash_tree_root` of a `BeaconBlockHeader`
+pub fn header_root(header: &Header) -> B256 {
+    let message = &header.message;
+    let leaves = [
+        pad_chunk(&message.slot.to_le_bytes()),
+        pad_chunk(&message.proposer_index.to_le_bytes()),
+        message.parent_root.0,
+        message.state_root.0,
+        message.body_root.0,
+        [0u8; 32],
+        [0u8; 32],
+        [0u8; 32],
+    ];
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+    }
+    B256::from(layer[0])
+}
+
+fn pad_chunk(bytes: &[u8]) -> [u8; 32] {
+    let mut chunk = [0u8; 32];
+    chunk[..bytes.len()].copy_from_slice(bytes);
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_rpc_types_beacon::sidecar::BeaconBlobBundle;
+    use blst::min_pk::SecretKey;
+
+    /// Sidecars of Holesky slot 1409759, as served by a beacon node
+    fn deneb_sidecars() -> Vec<BlobData> {
+        let fixture = include_str!("../../mock_cl/example_blob_sidecar.json");
+        serde_json::from_str::<BeaconBlobBundle>(fixture).unwrap().data
+    }
+
+    #[test]
+    fn verifies_deneb_sidecars() {
+        let sidecars = deneb_sidecars();
+        assert_eq!(sidecars.len(), 6);
+        for sidecar in &sidecars {
+            verify_commitment_inclusion(sidecar).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_other_body_root() {
+        let mut sidecar = deneb_sidecars().remove(0);
+        sidecar.signed_block_header.message.body_root = B256::repeat_byte(1);
+        assert!(matches!(
+            verify_commitment_inclusion(&sidecar),
+            Err(InclusionError::NotIncluded(0, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_index() {
+        let mut sidecar = deneb_sidecars().remove(0);
+        sidecar.index = 1;
+        assert!(matches!(
+            verify_commitment_inclusion(&sidecar),
+            Err(InclusionError::NotIncluded(1, _))
+        ));
+        sidecar.index = 1 << BLOB_COMMITMENTS_LIST_DEPTH;
+        assert!(matches!(
+            verify_commitment_inclusion(&sidecar),
+            Err(InclusionError::IndexOutOfRange(_))
+        ));
+    }
+
+    /// Holesky's signing domain, with `key` as the key of proposer 110239
+    fn holesky_validators(key: &SecretKey) -> ValidatorSet {
+        ValidatorSet {
+            genesis_validators_root:
+                "0x9143aa7c615a7f7115e2b6aac319c03529df8242ae705fba9df39b79c59fa8b1"
+                    .parse()
+                    .unwrap(),
+            fork_version: FixedBytes::from([0x05, 0x01, 0x70, 0x00]),
+            validators: HashMap::from([(110239, Bytes::from(key.sk_to_pk().to_bytes().to_vec()))]),
+        }
+    }
+
+    fn key(seed: u8) -> SecretKey {
+        SecretKey::key_gen(&[seed; 32], &[]).unwrap()
+    }
+
+    /// The header of the fixture signed by `key` under `validators`
+    fn signed_header(validators: &ValidatorSet, key: &SecretKey) -> Header {
+        let mut header = deneb_sidecars().remove(0).signed_block_header;
+        let signing_root = validators.signing_root(&header);
+        header.signature = key.sign(signing_root.as_slice(), BLS_DST, &[]).to_bytes().into();
+        header
+    }
+
+    #[test]
+    fn computes_the_signing_root_of_a_holesky_header() {
+        let validators = holesky_validators(&key(1));
+        let header = deneb_sidecars().remove(0).signed_block_header;
+        // Computed independently from the SSZ containers of the spec
+        let expected_root: B256 =
+            "0xc7d9a15af18c4e4db0f69d1e3d6b6f2b8b1ffea2291f65bb4134bbc4dece8782".parse().unwrap();
+        let expected_domain: B256 =
+            "0x0000000069ae0e9900d509b38350c53915fccde15c6ef44214aa1b5bdec34d3a".parse().unwrap();
+        let expected_signing_root: B256 =
+            "0xeedc1651a93167ec10f16385aa985fd9c7e4b03ad8bcb9ee14888a37cbfead41".parse().unwrap();
+        assert_eq!(header_root(&header), expected_root);
+        assert_eq!(validators.domain(), expected_domain);
+        assert_eq!(validators.signing_root(&header), expected_signing_root);
+    }
+
+    #[test]
+    fn verifies_header_signatures() {
+        let validators = holesky_validators(&key(1));
+        let header = signed_header(&validators, &key(1));
+        validators.verify_header(&header).unwrap();
+
+        let verifier = InclusionVerifier { check_block_root: true, validators: Some(validators) };
+        let mut sidecars = deneb_sidecars();
+        for sidecar in &mut sidecars {
+            sidecar.signed_block_header = header.clone();
+        }
+        verifier.verify(header_root(&header), &sidecars).unwrap();
+    }
+
+    #[test]
+    fn rejects_tampered_header_signatures() {
+        let validators = holesky_validators(&key(1));
+        let header = signed_header(&validators, &key(1));
+
+        let mut signature = [0u8; 96];
+        signature.copy_from_slice(&header.signature);
+        signature[95] ^= 1;
+        let mut tampered = header.clone();
+        tampered.signature = signature.into();
+        assert!(matches!(
+            validators.verify_header(&tampered),
+            Err(InclusionError::InvalidSignature(110239))
+        ));
+
+        // A signature over another header, by another key or under another fork does not hold
+        let mut other_slot = header.clone();
+        other_slot.message.slot += 1;
+        let other_key = signed_header(&validators, &key(2));
+        let other_fork = ValidatorSet {
+            fork_version: FixedBytes::from([0x04, 0x01, 0x70, 0x00]),
+            ..holesky_validators(&key(1))
+        };
+        for (validators, header) in
+            [(&validators, &other_slot), (&validators, &other_key), (&other_fork, &header)]
+        {
+            assert!(matches!(
+                validators.verify_header(header),
+                Err(InclusionError::InvalidSignature(110239))
+            ));
+        }
+
+        let mut unknown = header;
+        unknown.message.proposer_index = 1;
+        assert!(matches!(
+            validators.verify_header(&unknown),
+            Err(InclusionError::UnknownProposer(1))
+        ));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::sequencer::{
+    coding::{CodingScheme, FIELD_ELEMENTS_PER_BLOB},
+    fft::{bit_reversed, reverse_bits, scaled},
+    sequencer::SequencerError,
+    utils::be_bytes_to_field_elements,
+};
+use alloy_eips::eip4844::Bytes48;
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup, VariableBaseMSM};
+use ark_ff::{Field, Zero};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use rayon::prelude::*;
+use reth::primitives::{constants::eip4844::MAINNET_KZG_TRUSTED_SETUP, kzg::KzgSettings};
+use revm_primitives::kzg::{parse_kzg_trusted_setup, G1_POINTS, G2_POINTS};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TrustedSetupError {
+    #[error("Failed to load trusted setup from {0}: {1:?}")]
+    Load(PathBuf, reth::primitives::kzg::Error),
+
+    #[error("Failed to read trusted setup from {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    #[error("Failed to parse trusted setup from {0}: {1}")]
+    Parse(PathBuf, String),
+
+    #[error("Trusted setup has an invalid point: {0}")]
+    Point(SerializationError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CellProofError {
+    #[error("Commitment is not a valid G1 point")]
+    Commitment,
+
+    #[error("Proof is not a valid G1 point")]
+    Proof,
+
+    #[error("Cells of {0} field elements are too large to verify")]
+    CellSize(usize),
+
+    #[error("Invalid cell: {0}")]
+    Cell(#[from] SequencerError),
+
+    #[error("Proof does not open the commitment at the cell")]
+    Mismatch,
+}
+
+/// Load the KZG trusted setup used to verify sidecars
+///
+/// Falls back to the Ethereum mainnet setup when no file is given.
+pub fn load_trusted_setup(path: Option<&Path>) -> Result<Arc<KzgSettings>, TrustedSetupError> {
+    match path {
+        Some(path) => {
+            let settings = KzgSettings::load_trusted_setup_file(path)
+                .map_err(|e| TrustedSetupError::Load(path.to_path_buf(), e))?;
+            println!("Loaded trusted setup from {}", path.display());
+            Ok(Arc::new(settings))
+        }
+        None => Ok(MAINNET_KZG_TRUSTED_SETUP.clone()),
+    }
+}
+
+/// Load the setup cell proofs are made with, from the same file as [`load_trusted_setup`]
+pub fn load_cell_setup(path: Option<&Path>) -> Result<Arc<CellSetup>, TrustedSetupError> {
+    let setup = match path {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| TrustedSetupError::Read(path.to_path_buf(), e))?;
+            let (g1, g2) = parse_kzg_trusted_setup(&contents)
+                .map_err(|e| TrustedSetupError::Parse(path.to_path_buf(), format!("{:?}", e)))?;
+            CellSetup::from_points(&g1.0, &g2.0)?
+        }
+        None => CellSetup::from_points(&G1_POINTS.0, &G2_POINTS.0)?,
+    };
+    Ok(Arc::new(setup))
+}
+
+/// Trusted setup in monomial form, for KZG proofs over the cells of field element coded blobs
+///
+/// A cell holds the blob polynomial's evaluations over a coset `h * H` of the subgroup of its
+/// size. Its proof is the commitment to the quotient of the polynomial by `x^k - h^k`, which
+/// storage nodes check against the blob commitment with a single pairing equation.
+#[derive(Debug)]
+pub struct CellSetup {
+    /// `[τ^i]₁` for every coefficient of a blob polynomial
+    g1: Vec<G1Affine>,
+    /// `[τ^i]₂`, bounding the cell size to one less than their number
+    g2: Vec<G2Affine>,
+    blob_domain: Radix2EvaluationDomain<Fr>,
+    extended_domain: Radix2EvaluationDomain<Fr>,
+    /// Transformed `g1` per cell size, see [`CellSetup::prove_cells`]
+    cell_bases: Mutex<HashMap<usize, Arc<Vec<Vec<G1Affine>>>>>,
+}
+
+impl CellSetup {
+    /// Build from EIP-4844 setup points: G1 in bit-reversed Lagrange form, G2 in monomial form
+    pub fn from_points(
+        g1_lagrange: &[[u8; 48]],
+        g2_monomial: &[[u8; 96]],
+    ) -> Result<Self, TrustedSetupError> {
+        // The setup is trusted, so the subgroup checks are skipped
+        let g1_lagrange = g1_lagrange
+            .par_iter()
+            .map(|point| G1Affine::deserialize_compressed_unchecked(&point[..]).map(Into::into))
+            .collect::<Result<Vec<G1Projective>, _>>()
+            .map_err(TrustedSetupError::Point)?;
+        let g2 = g2_monomial
+            .iter()
+            .map(|point| G2Affine::deserialize_compressed_unchecked(&point[..]))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(TrustedSetupError::Point)?;
+
+        // The Lagrange basis evaluated at τ, transformed like any evaluations, gives τ's powers
+        let blob_domain = Radix2EvaluationDomain::new(FIELD_ELEMENTS_PER_BLOB)
+            .expect("the scalar field has a 4096 element domain");
+        let g1 = G1Projective::normalize_batch(&blob_domain.fft(&bit_reversed(&g1_lagrange)));
+
+        Ok(Self {
+            g1,
+            g2,
+            blob_domain,
+            extended_domain: Radix2EvaluationDomain::new(2 * FIELD_ELEMENTS_PER_BLOB)
+                .expect("the scalar field has a 8192 element domain"),
+            cell_bases: Mutex::default(),
+        })
+    }
+
+    /// Proof of every cell of a blob under a validated field element scheme, in shard order
+    ///
+    /// Computed all at once as in FK20. With `k` elements per cell and `n / k = L` rows, the proof
+    /// at `x^k - a` is `Σ a^m H_m`, where `H_m = Σ_r Σ_j p[(j + m + 1)k + r] [τ^(jk + r)]₁` is a
+    /// Toeplitz product done with FFTs of size `2L`. The `a` of the cells are the `2L`th roots of
+    /// unity in bit-reversed order, so one more FFT over the `H_m` yields every proof.
+    pub fn prove_cells(
+        &self,
+        blob: &[u8],
+        scheme: &CodingScheme,
+    ) -> Result<Vec<Bytes48>, SequencerError> {
+        let cell_size = FIELD_ELEMENTS_PER_BLOB / scheme.data_shards;
+        let rows = scheme.data_shards;
+        let elements = be_bytes_to_field_elements(blob)?;
+        let coefficients = self.blob_domain.ifft(&bit_reversed(&elements));
+
+        let bases = self.cell_bases(cell_size);
+        let domain = Radix2EvaluationDomain::<Fr>::new(2 * rows).expect("rows divide the domain");
+        // Coefficients of each residue mod k, reversed so the Toeplitz product is a convolution
+        let transformed: Vec<Vec<Fr>> = (0..cell_size)
+            .into_par_iter()
+            .map(|r| {
+                let row: Vec<Fr> =
+                    (0..rows).map(|t| coefficients[(rows - 1 - t) * cell_size + r]).collect();
+                domain.fft(&row)
+            })
+            .collect();
+        let product: Vec<G1Projective> = (0..2 * rows)
+            .into_par_iter()
+            .map(|f| {
+                let scalars: Vec<Fr> = transformed.iter().map(|row| row[f]).collect();
+                G1Projective::msm_unchecked(&bases[f], &scalars)
+            })
+            .collect();
+        let convolution = domain.ifft(&product);
+
+        // H_m, zero padded to the number of cells
+        let mut quotients = vec![G1Projective::zero(); 2 * rows];
+        for (m, quotient) in quotients.iter_mut().take(rows - 1).enumerate() {
+            *quotient = convolution[rows - 2 - m];
+        }
+        let proofs = G1Projective::normalize_batch(&bit_reversed(&domain.fft(&quotients)));
+        Ok(proofs.into_iter().map(g1_to_bytes).collect())
+    }
+
+    /// Check that a cell of a blob opens its commitment
+    pub fn verify_cell(
+        &self,
+        commitment: &Bytes48,
+        cell_index: usize,
+        cell: &[u8],
+        proof: &Bytes48,
+        scheme: &CodingScheme,
+    ) -> Result<(), CellProofError> {
+        let cell_size = FIELD_ELEMENTS_PER_BLOB / scheme.data_shards;
+        if cell_size >= self.g2.len() {
+            return Err(CellProofError::CellSize(cell_size));
+        }
+        if cell_index >= scheme.total_shards() {
+            return Err(SequencerError::Shard(cell_index).into());
+        }
+        let commitment = G1Affine::deserialize_compressed(&commitment[..])
+            .map_err(|_| CellProofError::Commitment)?;
+        let proof =
+            G1Affine::deserialize_compressed(&proof[..]).map_err(|_| CellProofError::Proof)?;
+        let values = be_bytes_to_field_elements(cell)?;
+        if values.len() != cell_size {
+            return Err(SequencerError::Shard(cell_index).into());
+        }
+
+        // Cell values are the evaluations at h times the cell sized subgroup, bit-reversed
+        let shift = self.coset_shift(cell_index, cell_size);
+        let cell_domain =
+            Radix2EvaluationDomain::<Fr>::new(cell_size).expect("cell sizes divide the domain");
+        let interpolation = scaled(
+            &cell_domain.ifft(&bit_reversed(&values)),
+            shift.inverse().expect("roots of unity are not zero"),
+        );
+        let interpolation = G1Projective::msm_unchecked(&self.g1, &interpolation);
+
+        // e(C - [I(τ)]₁, [1]₂) = e(π, [τ^k - h^k]₂)
+        let g2 = self.g2[0];
+        let vanishing = self.g2[cell_size].into_group() - g2 * shift.pow([cell_size as u64]);
+        let pairing = Bls12_381::multi_pairing(
+            [(commitment.into_group() - interpolation).into_affine(), (-proof.into_group()).into()],
+            [g2, vanishing.into_affine()],
+        );
+        if !pairing.is_zero() {
+            return Err(CellProofError::Mismatch);
+        }
+        Ok(())
+    }
+
+    /// Setup points of each residue mod `cell_size`, FFT'd to twice their length and transposed
+    fn cell_bases(&self, cell_size: usize) -> Arc<Vec<Vec<G1Affine>>> {
+        let mut cache = self.cell_bases.lock().unwrap();
+        if let Some(bases) = cache.get(&cell_size) {
+            return bases.clone();
+        }
+
+        let rows = FIELD_ELEMENTS_PER_BLOB / cell_size;
+        let domain = Radix2EvaluationDomain::<Fr>::new(2 * rows).expect("rows divide the domain");
+        let transformed: Vec<Vec<G1Projective>> = (0..cell_size)
+            .into_par_iter()
+            .map(|r| {
+                let row: Vec<G1Projective> =
+                    (0..rows).map(|j| self.g1[j * cell_size + r].into_group()).collect();
+                domain.fft(&row)
+            })
+            .collect();
+        let bases: Vec<Vec<G1Affine>> = (0..2 * rows)
+            .map(|f| {
+                let column: Vec<G1Projective> = transformed.iter().map(|row| row[f]).collect();
+                G1Projective::normalize_batch(&column)
+            })
+            .collect();
+
+        let bases = Arc::new(bases);
+        cache.insert(cell_size, bases.clone());
+        bases
+    }
+
+    /// Root of unity `h` such that a cell covers `h` times the subgroup of the cell's size
+    fn coset_shift(&self, cell_index: usize, cell_size: usize) -> Fr {
+        let size = self.extended_domain.size();
+        self.extended_domain.element(reverse_bits(cell_index * cell_size, size))
+    }
+}
+
+fn g1_to_bytes(point: G1Affine) -> Bytes48 {
+    let mut bytes = Bytes48::ZERO;
+    point.serialize_compressed(&mut bytes.0[..]).expect("a compressed G1 point is 48 bytes");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::{coding::CodingAlgorithm, fft::FieldElementCodec};
+    use ark_ec::Group;
+    use ark_ff::{PrimeField, UniformRand};
+    use ark_std::test_rng;
+    use std::sync::OnceLock;
+
+    /// Largest cell size a setup of this many G2 points supports
+    const CELL_SIZE: usize = 64;
+
+    const SCHEME: CodingScheme = CodingScheme {
+        algorithm: CodingAlgorithm::FieldElementFft,
+        data_shards: FIELD_ELEMENTS_PER_BLOB / CELL_SIZE,
+        parity_shards: FIELD_ELEMENTS_PER_BLOB / CELL_SIZE,
+    };
+
+    /// Setup with a known secret, laid out like the EIP-4844 one but with few G2 points
+    fn setup(tau: Fr) -> CellSetup {
+        let domain = Radix2EvaluationDomain::<Fr>::new(FIELD_ELEMENTS_PER_BLOB).unwrap();
+        let lagrange: Vec<G1Projective> =
+            bit_reversed(&domain.evaluate_all_lagrange_coefficients(tau))
+                .par_iter()
+                .map(|l| G1Projective::generator() * l)
+                .collect();
+        let g1: Vec<[u8; 48]> = G1Projective::normalize_batch(&lagrange)
+            .into_iter()
+            .map(|point| g1_to_bytes(point).0)
+            .collect();
+        let g2: Vec<[u8; 96]> = std::iter::successors(Some(Fr::ONE), |power| Some(*power * tau))
+            .take(CELL_SIZE + 1)
+            .map(|power| {
+                let mut bytes = [0u8; 96];
+                (G2Affine::generator() * power)
+                    .into_affine()
+                    .serialize_compressed(&mut bytes[..])
+                    .unwrap();
+                bytes
+            })
+            .collect();
+        CellSetup::from_points(&g1, &g2).unwrap()
+    }
+
+    /// A random blob and its commitment under the setup of `tau`
+    fn blob(tau: Fr) -> (Vec<u8>, Bytes48) {
+        let mut rng = test_rng();
+        let elements: Vec<Fr> = (0..FIELD_ELEMENTS_PER_BLOB).map(|_| Fr::rand(&mut rng)).collect();
+        let domain = Radix2EvaluationDomain::<Fr>::new(FIELD_ELEMENTS_PER_BLOB).unwrap();
+        let coefficients = domain.ifft(&bit_reversed(&elements));
+        let evaluation = coefficients.iter().rev().fold(Fr::zero(), |acc, c| acc * tau + c);
+        let commitment = g1_to_bytes((G1Projective::generator() * evaluation).into_affine());
+        (crate::sequencer::utils::field_elements_to_be_bytes(&elements), commitment)
+    }
+
+    /// Setup, commitment, cells and cell proofs of a random blob
+    struct ProvedBlob {
+        setup: CellSetup,
+        commitment: Bytes48,
+        cells: Vec<Vec<u8>>,
+        proofs: Vec<Bytes48>,
+    }
+
+    /// Shared by the tests, as transforming the setup dominates their run time
+    fn proved_blob() -> &'static ProvedBlob {
+        static PROVED: OnceLock<ProvedBlob> = OnceLock::new();
+        PROVED.get_or_init(|| {
+            let tau = Fr::from_be_bytes_mod_order(b"cell proof test secret");
+            let setup = setup(tau);
+            let (blob, commitment) = blob(tau);
+            let extended = FieldElementCodec::new(&SCHEME).encode(&blob).unwrap();
+            let cells = extended.chunks(SCHEME.shard_size()).map(<[u8]>::to_vec).collect();
+            let proofs = setup.prove_cells(&blob, &SCHEME).unwrap();
+            ProvedBlob { setup, commitment, cells, proofs }
+        })
+    }
+
+    #[test]
+    fn verifies_every_cell() {
+        let ProvedBlob { setup, commitment, cells, proofs } = proved_blob();
+        assert_eq!(cells.len(), SCHEME.total_shards());
+        assert_eq!(proofs.len(), SCHEME.total_shards());
+        for (index, (cell, proof)) in cells.iter().zip(proofs).enumerate() {
+            setup.verify_cell(commitment, index, cell, proof, &SCHEME).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_a_flipped_byte() {
+        let ProvedBlob { setup, commitment, cells, proofs } = proved_blob();
+        let mut cell = cells[3].clone();
+        cell[100] ^= 1;
+        assert!(matches!(
+            setup.verify_cell(commitment, 3, &cell, &proofs[3], &SCHEME),
+            Err(CellProofError::Mismatch)
+        ));
+    }
+
+    #[test]
+    fn rejects_a_wrong_index() {
+        let ProvedBlob { setup, commitment, cells, proofs } = proved_blob();
+        assert!(matches!(
+            setup.verify_cell(commitment, 4, &cells[3], &proofs[3], &SCHEME),
+            Err(CellProofError::Mismatch)
+        ));
+        assert!(matches!(
+            setup.verify_cell(commitment, SCHEME.total_shards(), &cells[3], &proofs[3], &SCHEME),
+            Err(CellProofError::Cell(SequencerError::Shard(_)))
+        ));
+    }
+}
@@ -1,6 +1,26 @@
+pub mod beacon;
 pub mod blobs;
+pub mod checkpoint;
 pub mod codec;
+pub mod config;
+pub mod dispatch;
+pub mod distributed;
+pub mod filter;
+pub mod inclusion;
+pub mod kzg;
+pub mod merkle;
+pub mod outbox;
+pub mod persist;
+pub mod quarantine;
+pub mod registry;
+pub mod repair;
+pub mod retention;
+pub mod retrieve;
+pub mod retry;
+pub mod scrub;
 pub mod sequencer;
+pub mod storage;
+pub mod time;
 pub mod proto {
     tonic::include_proto!("exex");
 }
@@ -0,0 +1,159 @@
+use crate::outbox::shard_hash;
+use reth::primitives::B256;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Binary SHA-256 Merkle tree over the shards of a blob
+///
+/// Leaves are the shard hashes storage nodes acknowledge with, padded with zero leaves to a power
+/// of two. Branches are checked the same way as sidecar inclusion proofs.
+#[derive(Debug, Clone)]
+pub struct ShardTree {
+    /// Leaves first, the root last
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl ShardTree {
+    pub fn new<T: AsRef<[u8]>>(shards: &[T]) -> Self {
+        let mut leaves: Vec<[u8; 32]> =
+            shards.iter().map(|shard| shard_hash(shard.as_ref()).0).collect();
+        leaves.resize(leaves.len().next_power_of_two(), [0u8; 32]);
+
+        let mut layers = vec![leaves];
+        while let Some(layer) = layers.last().filter(|layer| layer.len() > 1) {
+            let parents = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            layers.push(parents);
+        }
+        Self { layers }
+    }
+
+    pub fn root(&self) -> B256 {
+        B256::from(self.layers[self.layers.len() - 1][0])
+    }
+
+    /// Root and the sibling of every node from shard `index` up to it
+    pub fn proof(&self, index: usize) -> MerkleProof {
+        let branch = self.layers[..self.layers.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(depth, layer)| B256::from(layer[(index >> depth) ^ 1]))
+            .collect();
+        MerkleProof { root: self.root(), branch }
+    }
+}
+
+/// Inclusion proof of a shard in the tree over its blob's shards
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub root: B256,
+    pub branch: Vec<B256>,
+}
+
+impl MerkleProof {
+    /// Parse the proof sent with a shard, `None` when it is missing or malformed
+    pub fn from_bytes(root: &[u8], branch: &[Vec<u8>]) -> Option<Self> {
+        Some(Self {
+            root: B256::try_from(root).ok()?,
+            branch: branch
+                .iter()
+                .map(|sibling| B256::try_from(sibling.as_slice()).ok())
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    pub fn branch_bytes(&self) -> Vec<Vec<u8>> {
+        self.branch.iter().map(|sibling| sibling.to_vec()).collect()
+    }
+
+    /// Check that `shard` is shard `index` of a blob split into `total_shards`
+    pub fn verify(&self, shard: &[u8], index: usize, total_shards: usize) -> bool {
+        index < total_shards
+            && self.branch.len() == total_shards.next_power_of_two().trailing_zeros() as usize
+            && is_valid_merkle_branch(shard_hash(shard).0, &self.branch, index as u64, self.root)
+    }
+}
+
+pub(crate) fn is_valid_merkle_branch(
+    leaf: [u8; 32],
+    branch: &[B256],
+    index: u64,
+    root: B256,
+) -> bool {
+    let mut value = leaf;
+    for (depth, sibling) in branch.iter().enumerate() {
+        value = if (index >> depth) & 1 == 1 {
+            hash_pair(&sibling.0, &value)
+        } else {
+            hash_pair(&value, &sibling.0)
+        };
+    }
+    B256::from(value) == root
+}
+
+pub(crate) fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shards(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|index| vec![index as u8; 64]).collect()
+    }
+
+    #[test]
+    fn proves_every_shard() {
+        for count in [1, 2, 5, 6, 8, 160] {
+            let shards = shards(count);
+            let tree = ShardTree::new(&shards);
+            for (index, shard) in shards.iter().enumerate() {
+                let proof = tree.proof(index);
+                assert_eq!(proof.root, tree.root());
+                assert!(proof.verify(shard, index, count), "shard {index} of {count}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_a_wrong_index() {
+        let shards = shards(5);
+        let tree = ShardTree::new(&shards);
+        let proof = tree.proof(2);
+        assert!(!proof.verify(&shards[2], 3, 5));
+        assert!(!proof.verify(&shards[2], 6, 5));
+        // A padding leaf is never a shard
+        assert!(!tree.proof(5).verify(&[], 5, 5));
+    }
+
+    #[test]
+    fn rejects_a_wrong_root() {
+        let shards = shards(6);
+        let mut proof = ShardTree::new(&shards).proof(4);
+        proof.root = ShardTree::new(&shards[..5]).root();
+        assert!(!proof.verify(&shards[4], 4, 6));
+    }
+
+    #[test]
+    fn rejects_a_wrong_shard_or_branch() {
+        let shards = shards(6);
+        let proof = ShardTree::new(&shards).proof(1);
+        assert!(!proof.verify(&shards[0], 1, 6));
+        assert!(!proof.verify(&shards[1], 1, 16));
+
+        let mut tampered = proof.clone();
+        tampered.branch[1] = B256::ZERO;
+        assert!(!tampered.verify(&shards[1], 1, 6));
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let proof = ShardTree::new(&shards(6)).proof(3);
+        let parsed = MerkleProof::from_bytes(proof.root.as_slice(), &proof.branch_bytes());
+        assert_eq!(parsed, Some(proof));
+        assert_eq!(MerkleProof::from_bytes(&[0u8; 31], &[]), None);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::{
+    dispatch::BlobHeader,
+    merkle::MerkleProof,
+    persist::{read_json, remove_if_exists, write_atomic, write_json, JsonStore},
+    sequencer::{coding::CodingScheme, sequencer::EncodedBlob},
+    time::unix_now_ms,
+};
+use alloy_eips::eip4844::Bytes48;
+use reth::primitives::{Address, B256};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// A shard sent to a storage node that did not acknowledge it yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutstandingShard {
+    pub node_id: u32,
+    /// SHA-256 of the shard, the node must acknowledge with the same hash
+    pub hash: B256,
+    pub attempts: u32,
+    /// Unix time in milliseconds of the last delivery
+    pub sent_at_ms: u64,
+    /// KZG cell proof sent along with the shard, for field element schemes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<Bytes48>,
+    /// Inclusion proof in the blob's shard tree sent along with the shard
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle: Option<MerkleProof>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OutboxBlob {
+    scheme: CodingScheme,
+    block_number: u64,
+    block_hash: B256,
+    sender: Option<Address>,
+    /// Regenerated by repair rather than queued with its block, so its block is long finished
+    repair: bool,
+    /// Shards of the blob each node acknowledged or held before they were queued
+    held: BTreeMap<u32, usize>,
+    shards: BTreeMap<u32, OutstandingShard>,
+}
+
+/// Retraction of a blob from an orphaned block a storage node did not acknowledge yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutstandingRetraction {
+    pub name: String,
+    pub block_hash: B256,
+    pub node_id: u32,
+    /// Unix time in milliseconds of the last delivery
+    pub sent_at_ms: u64,
+}
+
+/// Outcome of a storage node acknowledging a shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ack {
+    /// The shard is stored, and so is every other shard of its blob if `blob_complete`
+    Accepted { blob_complete: bool },
+    /// The shard is not outstanding on this node, e.g. it was already re-placed
+    Unknown,
+    /// The node stored different bytes than were sent
+    HashMismatch,
+}
+
+/// Shards waiting for their storage node's acknowledgement
+///
+/// Shard bytes are kept in the outbox directory until acknowledged, so they can be delivered again
+/// after a restart without refetching the blob. Each blob's outstanding shards are kept in a JSON
+/// file of their own, so settling a shard only rewrites the file of its blob. Retractions of blobs
+/// from orphaned blocks are kept in `retractions.json` until every node acknowledges them.
+#[derive(Debug)]
+pub struct Outbox {
+    dir: PathBuf,
+    blobs: BTreeMap<String, OutboxBlob>,
+    retractions: JsonStore<Vec<OutstandingRetraction>>,
+}
+
+impl Outbox {
+    /// Load the outbox from `dir`, starting empty if it does not exist yet
+    ///
+    /// Unreadable blob files are skipped, their shards are then never delivered again.
+    pub fn load(dir: &Path) -> std::io::Result<Self> {
+        let retractions_path = dir.join("retractions.json");
+        let mut outbox = Self {
+            dir: dir.to_path_buf(),
+            blobs: BTreeMap::new(),
+            retractions: JsonStore::load(&retractions_path)?,
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(outbox),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path == retractions_path
+                || path.extension().and_then(|ext| ext.to_str()) != Some("json")
+            {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+            match read_json(&path) {
+                Ok(Some(blob)) => {
+                    outbox.blobs.insert(name.to_string(), blob);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Skipping unreadable outbox entry {}: {}", path.display(), e),
+            }
+        }
+        Ok(outbox)
+    }
+
+    /// Record the shards of a blob as sent, `placement` holds the node of every shard
+    pub fn add(
+        &mut self,
+        header: &BlobHeader<'_>,
+        blob: &EncodedBlob,
+        placement: &[u32],
+    ) -> std::io::Result<()> {
+        let shards: Vec<(usize, u32)> = placement.iter().copied().enumerate().collect();
+        self.queue(header, blob, &shards, None)
+    }
+
+    /// Record shards regenerated by repair as sent, each with its node, `held` counts the
+    /// shards of the blob each node still holds
+    pub fn add_shards(
+        &mut self,
+        header: &BlobHeader<'_>,
+        blob: &EncodedBlob,
+        shards: &[(usize, u32)],
+        held: &HashMap<u32, usize>,
+    ) -> std::io::Result<()> {
+        self.queue(header, blob, shards, Some(held))
+    }
+
+    fn queue(
+        &mut self,
+        header: &BlobHeader<'_>,
+        blob: &EncodedBlob,
+        shards: &[(usize, u32)],
+        repair: Option<&HashMap<u32, usize>>,
+    ) -> std::io::Result<()> {
+        let name = header.name;
+        let now = unix_now_ms();
+        let mut outstanding = BTreeMap::new();
+        for &(index, node_id) in shards {
+            let shard = &blob.shards[index];
+            write_atomic(&self.shard_path(name, index as u32), shard)?;
+            outstanding.insert(
+                index as u32,
+                OutstandingShard {
+                    node_id,
+                    hash: shard_hash(shard),
+                    attempts: 1,
+                    sent_at_ms: now,
+                    proof: blob.proofs.get(index).copied(),
+                    merkle: Some(blob.tree.proof(index)),
+                },
+            );
+        }
+        self.blobs
+            .entry(name.to_string())
+            .or_insert_with(|| OutboxBlob {
+                scheme: header.scheme,
+                block_number: header.block_number,
+                block_hash: header.block_hash,
+                sender: header.sender,
+                repair: repair.is_some(),
+                held: repair.into_iter().flatten().map(|(node, held)| (*node, *held)).collect(),
+                shards: BTreeMap::new(),
+            })
+            .shards
+            .extend(outstanding);
+        self.save_blob(name)
+    }
+
+    /// Settle a shard acknowledged by a storage node
+    pub fn ack(
+        &mut self,
+        name: &str,
+        index: u32,
+        node_id: u32,
+        hash: B256,
+    ) -> std::io::Result<Ack> {
+        let Some(blob) = self.blobs.get_mut(name) else { return Ok(Ack::Unknown) };
+        match blob.shards.get(&index) {
+            Some(shard) if shard.node_id != node_id => return Ok(Ack::Unknown),
+            Some(shard) if shard.hash != hash => return Ok(Ack::HashMismatch),
+            Some(_) => {}
+            None => return Ok(Ack::Unknown),
+        }
+
+        blob.shards.remove(&index);
+        *blob.held.entry(node_id).or_default() += 1;
+        let blob_complete = blob.shards.is_empty();
+        if blob_complete {
+            self.blobs.remove(name);
+        }
+        self.save_blob(name)?;
+        remove_if_exists(&self.shard_path(name, index))?;
+        Ok(Ack::Accepted { blob_complete })
+    }
+
+    /// Shards not acknowledged within `timeout` of their last delivery
+    pub fn expired(&self, timeout: Duration) -> Vec<(String, u32, OutstandingShard)> {
+        let deadline = unix_now_ms().saturating_sub(timeout.as_millis() as u64);
+        self.blobs
+            .iter()
+            .flat_map(|(name, blob)| {
+                blob.shards
+                    .iter()
+                    .filter(move |(_, shard)| shard.sent_at_ms <= deadline)
+                    .map(move |(index, shard)| (name.clone(), *index, shard.clone()))
+            })
+            .collect()
+    }
+
+    /// Shards of a blob each node holds or is being sent, leaving out the outstanding shards at
+    /// `excluding`
+    pub fn shards_per_node(&self, name: &str, excluding: &[u32]) -> HashMap<u32, usize> {
+        let Some(blob) = self.blobs.get(name) else { return HashMap::new() };
+        let mut load: HashMap<u32, usize> = blob.held.iter().map(|(n, h)| (*n, *h)).collect();
+        for (index, shard) in &blob.shards {
+            if !excluding.contains(index) {
+                *load.entry(shard.node_id).or_default() += 1;
+            }
+        }
+        load
+    }
+
+    /// Record another delivery of a shard to `node_id` and return its bytes
+    ///
+    /// A shard whose bytes are gone or unreadable is dropped from the outbox with a log line and
+    /// `None` returned, repair regenerates it from the other shards of its blob.
+    pub fn redeliver(
+        &mut self,
+        name: &str,
+        index: u32,
+        node_id: u32,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        let shard_path = self.shard_path(name, index);
+        let shard = match std::fs::read(&shard_path) {
+            Ok(shard) => shard,
+            Err(e) => {
+                eprintln!("Dropping shard {} of blob {} from the outbox: {}", index, name, e);
+                self.drop_shard(name, index)?;
+                remove_if_exists(&shard_path)?;
+                return Ok(None);
+            }
+        };
+        if let Some(outstanding) =
+            self.blobs.get_mut(name).and_then(|blob| blob.shards.get_mut(&index))
+        {
+            outstanding.attempts =
+                if outstanding.node_id == node_id { outstanding.attempts + 1 } else { 1 };
+            outstanding.node_id = node_id;
+            outstanding.sent_at_ms = unix_now_ms();
+        }
+        self.save_blob(name)?;
+        Ok(Some(shard))
+    }
+
+    // Forget an outstanding shard, and its blob once no shard of it is left
+    fn drop_shard(&mut self, name: &str, index: u32) -> std::io::Result<()> {
+        let Some(blob) = self.blobs.get_mut(name) else { return Ok(()) };
+        blob.shards.remove(&index);
+        if blob.shards.is_empty() {
+            self.blobs.remove(name);
+        }
+        self.save_blob(name)
+    }
+
+    /// What storage nodes are told about a queued blob along with its shards
+    pub fn header<'a>(&self, name: &'a str) -> Option<BlobHeader<'a>> {
+        self.blobs.get(name).map(|blob| BlobHeader {
+            name,
+            scheme: blob.scheme,
+            block_number: blob.block_number,
+            block_hash: blob.block_hash,
+            sender: blob.sender,
+        })
+    }
+
+    /// Drop a blob, e.g. because its block was orphaned
+    pub fn remove(&mut self, name: &str) -> std::io::Result<()> {
+        let Some(blob) = self.blobs.remove(name) else { return Ok(()) };
+        self.save_blob(name)?;
+        for index in blob.shards.keys() {
+            remove_if_exists(&self.shard_path(name, *index))?;
+        }
+        Ok(())
+    }
+
+    /// Drop a blob of an orphaned block and record its retraction as sent to `nodes`
+    pub fn retract(&mut self, name: &str, block_hash: B256, nodes: &[u32]) -> std::io::Result<()> {
+        if self.blobs.get(name).is_some_and(|blob| blob.block_hash == block_hash) {
+            self.remove(name)?;
+        }
+        let now = unix_now_ms();
+        self.retractions.update(|retractions| {
+            retractions.retain(|r| r.name != name || r.block_hash != block_hash);
+            retractions.extend(nodes.iter().map(|&node_id| OutstandingRetraction {
+                name: name.to_string(),
+                block_hash,
+                node_id,
+                sent_at_ms: now,
+            }));
+        })
+    }
+
+    /// Settle a retraction acknowledged by a storage node, `false` if it was not outstanding
+    pub fn ack_retraction(
+        &mut self,
+        name: &str,
+        block_hash: B256,
+        node_id: u32,
+    ) -> std::io::Result<bool> {
+        let matches = |r: &OutstandingRetraction| {
+            r.name == name && r.block_hash == block_hash && r.node_id == node_id
+        };
+        if !self.retractions.get().iter().any(matches) {
+            return Ok(false);
+        }
+        self.retractions.update(|retractions| retractions.retain(|r| !matches(r)))?;
+        Ok(true)
+    }
+
+    /// Retractions not acknowledged within `timeout` of their last delivery, recorded as
+    /// delivered again now
+    pub fn redeliver_retractions(
+        &mut self,
+        timeout: Duration,
+    ) -> std::io::Result<Vec<OutstandingRetraction>> {
+        let now = unix_now_ms();
+        let deadline = now.saturating_sub(timeout.as_millis() as u64);
+        let expired = |r: &OutstandingRetraction| r.sent_at_ms <= deadline;
+        if !self.retractions.get().iter().any(expired) {
+            return Ok(Vec::new());
+        }
+        self.retractions.update(|retractions| {
+            retractions
+                .iter_mut()
+                .filter(|r| expired(r))
+                .map(|r| {
+                    r.sent_at_ms = now;
+                    r.clone()
+                })
+                .collect()
+        })
+    }
+
+    /// Whether a retraction of the blob is still waiting for a node's acknowledgement
+    pub fn is_retracting(&self, name: &str) -> bool {
+        self.retractions.get().iter().any(|r| r.name == name)
+    }
+
+    /// Forget the retractions sent to a node that left the network for good
+    pub fn forget_retractions(&mut self, node_id: u32) -> std::io::Result<()> {
+        if self.retractions.get().iter().any(|r| r.node_id == node_id) {
+            self.retractions.update(|retractions| retractions.retain(|r| r.node_id != node_id))?;
+        }
+        Ok(())
+    }
+
+    /// Lowest block whose blobs still wait for an acknowledgement, repaired blobs aside
+    pub fn lowest_block(&self) -> Option<u64> {
+        self.blobs.values().filter(|blob| !blob.repair).map(|blob| blob.block_number).min()
+    }
+
+    /// Number of shards still waiting for an acknowledgement
+    pub fn len(&self) -> usize {
+        self.blobs.values().map(|blob| blob.shards.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+
+    /// Persist the outstanding shards of a blob, or forget it once none are left
+    fn save_blob(&self, name: &str) -> std::io::Result<()> {
+        let path = self.dir.join(format!("{}.json", name));
+        match self.blobs.get(name) {
+            Some(blob) => write_json(&path, blob),
+            None => remove_if_exists(&path),
+        }
+    }
+
+    fn shard_path(&self, name: &str, index: u32) -> PathBuf {
+        self.dir.join(format!("{}_{}.bin", name, index))
+    }
+}
+
+/// Hash a storage node acknowledges a shard with
+pub fn shard_hash(shard: &[u8]) -> B256 {
+    B256::from_slice(&Sha256::digest(shard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::ShardTree;
+    use bytes::Bytes;
+
+    fn blob(shards: usize) -> EncodedBlob {
+        let shards: Vec<Bytes> = (0..shards).map(|i| Bytes::from(vec![i as u8; 8])).collect();
+        EncodedBlob {
+            commitment: Bytes48::ZERO,
+            tree: ShardTree::new(&shards),
+            shards,
+            proofs: Vec::new(),
+        }
+    }
+
+    fn header(name: &str, block_hash: B256) -> BlobHeader<'_> {
+        BlobHeader {
+            name,
+            scheme: CodingScheme::default(),
+            block_number: 1,
+            block_hash,
+            sender: None,
+        }
+    }
+
+    #[test]
+    fn keeps_retractions_until_every_node_acknowledges_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let orphaned = B256::repeat_byte(1);
+        let mut outbox = Outbox::load(dir.path()).unwrap();
+        outbox.add(&header("a", orphaned), &blob(2), &[1, 2]).unwrap();
+        outbox.retract("a", orphaned, &[1, 2, 3]).unwrap();
+        assert!(outbox.header("a").is_none());
+        assert!(outbox.is_retracting("a"));
+
+        assert!(outbox.ack_retraction("a", orphaned, 1).unwrap());
+        assert!(!outbox.ack_retraction("a", B256::repeat_byte(2), 2).unwrap());
+        assert!(!outbox.ack_retraction("a", orphaned, 1).unwrap());
+        drop(outbox);
+
+        // Outstanding retractions survive a restart and are sent again once they time out
+        let mut outbox = Outbox::load(dir.path()).unwrap();
+        let resent = outbox.redeliver_retractions(Duration::ZERO).unwrap();
+        let nodes: Vec<u32> = resent.iter().map(|r| r.node_id).collect();
+        assert_eq!(nodes, vec![2, 3]);
+        assert!(outbox.redeliver_retractions(Duration::from_secs(60)).unwrap().is_empty());
+
+        outbox.forget_retractions(3).unwrap();
+        assert!(outbox.ack_retraction("a", orphaned, 2).unwrap());
+        assert!(!outbox.is_retracting("a"));
+    }
+
+    #[test]
+    fn keeps_a_blob_queued_again_from_another_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut outbox = Outbox::load(dir.path()).unwrap();
+        let canonical = B256::repeat_byte(2);
+        outbox.add(&header("a", canonical), &blob(2), &[1, 2]).unwrap();
+        outbox.retract("a", B256::repeat_byte(1), &[1, 2]).unwrap();
+        assert_eq!(outbox.header("a").map(|header| header.block_hash), Some(canonical));
+        assert_eq!(outbox.len(), 2);
+    }
+
+    #[test]
+    fn counts_acknowledged_and_outstanding_shards_per_node() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut outbox = Outbox::load(dir.path()).unwrap();
+        let blob = blob(4);
+        outbox.add(&header("a", B256::ZERO), &blob, &[1, 1, 2, 3]).unwrap();
+        outbox.ack("a", 0, 1, shard_hash(&blob.shards[0])).unwrap();
+        assert_eq!(outbox.shards_per_node("a", &[3]), HashMap::from([(1, 2), (2, 1)]));
+
+        let held = HashMap::from([(4, 3)]);
+        outbox.add_shards(&header("b", B256::ZERO), &blob, &[(0, 1)], &held).unwrap();
+        assert_eq!(outbox.shards_per_node("b", &[]), HashMap::from([(1, 1), (4, 3)]));
+        assert!(outbox.shards_per_node("c", &[]).is_empty());
+    }
+
+    #[test]
+    fn drops_shards_whose_bytes_are_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut outbox = Outbox::load(dir.path()).unwrap();
+        outbox.add(&header("a", B256::ZERO), &blob(2), &[1, 2]).unwrap();
+        std::fs::remove_file(dir.path().join("a_0.bin")).unwrap();
+
+        assert_eq!(outbox.redeliver("a", 0, 1).unwrap(), None);
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox.redeliver("a", 1, 3).unwrap(), Some(vec![1; 8]));
+        std::fs::remove_file(dir.path().join("a_1.bin")).unwrap();
+        assert_eq!(outbox.redeliver("a", 1, 3).unwrap(), None);
+        assert!(outbox.header("a").is_none());
+        assert!(Outbox::load(dir.path()).unwrap().is_empty());
+    }
+}
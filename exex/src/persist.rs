@@ -0,0 +1,129 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// A value kept in a JSON file, written through a temporary file on every save
+#[derive(Debug)]
+pub struct JsonStore<T> {
+    path: PathBuf,
+    value: T,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    /// Load the value from `path`, the default one if the file does not exist yet
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let value = read_json(path)?.unwrap_or_default();
+        Ok(Self { path: path.to_path_buf(), value })
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// The value to change in place, changes are persisted by the next `save`
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Change the value and persist it
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> std::io::Result<R> {
+        let result = f(&mut self.value);
+        self.save()?;
+        Ok(result)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        write_json(&self.path, &self.value)
+    }
+}
+
+/// Read a JSON file, `None` if it does not exist
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> std::io::Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write a value as pretty JSON, atomically
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// Write a file through a temporary one, so a crash never leaves it truncated
+///
+/// The directory is synced after the rename, so the new file has replaced the old one on disk
+/// once this returns.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    write_through_tmp(path, contents, OpenOptions::new())
+}
+
+/// Write a file like [`write_atomic`] that only its owner can read, e.g. a private key
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    // The mode only applies to new files, so a leftover temporary file must go first
+    remove_if_exists(&path.with_extension("tmp"))?;
+    write_through_tmp(path, contents, options)
+}
+
+fn write_through_tmp(
+    path: &Path,
+    contents: &[u8],
+    mut options: OpenOptions,
+) -> std::io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(parent)?;
+    let tmp = path.with_extension("tmp");
+    let mut file = options.write(true).create(true).truncate(true).open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    sync_dir(parent)
+}
+
+/// Make renames and new entries in a directory durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened as files to sync them outside unix
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+pub fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_private_files_for_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.key");
+        // A world-readable leftover of an interrupted write does not pass its mode on
+        std::fs::write(path.with_extension("tmp"), b"stale").unwrap();
+        write_private(&path, b"secret").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"secret");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
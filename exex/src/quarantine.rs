@@ -0,0 +1,61 @@
+use crate::{persist::JsonStore, time::unix_now};
+use reth::primitives::B256;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// A blob that was set aside instead of being distributed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedBlob {
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub transaction_hash: B256,
+    pub reason: String,
+    /// Unix time the blob was quarantined
+    pub quarantined_at: u64,
+}
+
+/// Blobs that could not be processed, kept for inspection instead of crashing the node
+#[derive(Debug)]
+pub struct Quarantine {
+    blobs: JsonStore<BTreeMap<String, QuarantinedBlob>>,
+}
+
+impl Quarantine {
+    /// Load the quarantine from `path`, starting empty if the file does not exist yet
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self { blobs: JsonStore::load(path)? })
+    }
+
+    /// Record a blob by its commitment, along with why it was quarantined
+    pub fn add(
+        &mut self,
+        name: &str,
+        block_number: u64,
+        block_hash: B256,
+        transaction_hash: B256,
+        reason: String,
+    ) -> std::io::Result<()> {
+        let blob = QuarantinedBlob {
+            block_number,
+            block_hash,
+            transaction_hash,
+            reason,
+            quarantined_at: unix_now(),
+        };
+        self.blobs.update(|blobs| {
+            blobs.insert(name.to_string(), blob);
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&QuarantinedBlob> {
+        self.blobs.get().get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blobs.get().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.get().is_empty()
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{persist::JsonStore, proto, time::unix_now};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// A storage node known to the ExEx
+#[derive(Debug, Clone)]
+pub struct StorageNode {
+    pub node_id: u32,
+    /// gRPC endpoint the node serves shards on
+    pub endpoint: String,
+    /// Bytes the node is willing to store, it takes no new shards once `used_bytes` reaches it
+    pub capacity: u64,
+    /// Key the node generated on its first start, listed so operators can tell nodes apart
+    pub public_key: Vec<u8>,
+    /// Bytes of shards the node stored, as of its last heartbeat
+    pub used_bytes: u64,
+    /// Unix time of the last registration or heartbeat
+    pub last_seen: u64,
+}
+
+impl StorageNode {
+    pub fn has_room(&self) -> bool {
+        self.used_bytes < self.capacity
+    }
+}
+
+/// Membership of the storage network, kept live by node heartbeats
+///
+/// Deregistered nodes are remembered, persisted, until they register again, so their heartbeats
+/// do not bring them back.
+#[derive(Debug, Clone)]
+pub struct NodeRegistry {
+    nodes: Arc<RwLock<BTreeMap<u32, StorageNode>>>,
+    deregistered: Arc<RwLock<JsonStore<BTreeSet<u32>>>>,
+    liveness_timeout: Duration,
+}
+
+impl NodeRegistry {
+    /// Load the deregistered nodes from `path`, every other node has to register again
+    pub fn load(path: &Path, liveness_timeout: Duration) -> std::io::Result<Self> {
+        let deregistered = Arc::new(RwLock::new(JsonStore::load(path)?));
+        Ok(Self { nodes: Arc::default(), deregistered, liveness_timeout })
+    }
+
+    /// Add a node, or refresh it if it registers again
+    pub fn register(
+        &self,
+        node_id: u32,
+        endpoint: String,
+        capacity: u64,
+        public_key: Vec<u8>,
+    ) -> std::io::Result<()> {
+        let mut deregistered = self.deregistered.write().unwrap();
+        if deregistered.get().contains(&node_id) {
+            deregistered.update(|ids| ids.remove(&node_id))?;
+        }
+        let node = StorageNode {
+            node_id,
+            endpoint,
+            capacity,
+            public_key,
+            used_bytes: 0,
+            last_seen: unix_now(),
+        };
+        self.nodes.write().unwrap().insert(node_id, node);
+        Ok(())
+    }
+
+    /// Record a heartbeat, returns false if the node is not registered
+    pub fn heartbeat(&self, node_id: u32, used_bytes: u64) -> bool {
+        match self.nodes.write().unwrap().get_mut(&node_id) {
+            Some(node) => {
+                node.used_bytes = used_bytes;
+                node.last_seen = unix_now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a node until it registers again, returns false if it was not registered
+    pub fn deregister(&self, node_id: u32) -> std::io::Result<bool> {
+        self.deregistered.write().unwrap().update(|ids| ids.insert(node_id))?;
+        Ok(self.nodes.write().unwrap().remove(&node_id).is_some())
+    }
+
+    pub fn is_deregistered(&self, node_id: u32) -> bool {
+        self.deregistered.read().unwrap().get().contains(&node_id)
+    }
+
+    pub fn get(&self, node_id: u32) -> Option<StorageNode> {
+        self.nodes.read().unwrap().get(&node_id).cloned()
+    }
+
+    pub fn is_live(&self, node: &StorageNode) -> bool {
+        unix_now().saturating_sub(node.last_seen) <= self.liveness_timeout.as_secs()
+    }
+
+    /// Ids of the nodes that sent a heartbeat recently, in ascending order
+    pub fn live_nodes(&self) -> Vec<u32> {
+        self.nodes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|node| self.is_live(node))
+            .map(|node| node.node_id)
+            .collect()
+    }
+
+    /// Live nodes with room left for shards, in ascending order
+    pub fn placeable_nodes(&self) -> Vec<u32> {
+        self.nodes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|node| self.is_live(node) && node.has_room())
+            .map(|node| node.node_id)
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<proto::NodeInfo> {
+        self.nodes
+            .read()
+            .unwrap()
+            .values()
+            .map(|node| proto::NodeInfo {
+                node_id: node.node_id,
+                endpoint: node.endpoint.clone(),
+                capacity: node.capacity,
+                public_key: node.public_key.clone(),
+                used_bytes: node.used_bytes,
+                last_seen: node.last_seen,
+                live: self.is_live(node),
+            })
+            .collect()
+    }
+}
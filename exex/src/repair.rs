@@ -0,0 +1,593 @@
+use crate::{
+    dispatch::{blob_chunk, BlobHeader, NodeQueues},
+    distributed::DistributedBlobs,
+    kzg::{load_cell_setup, load_trusted_setup, CellSetup, TrustedSetupError},
+    outbox::{shard_hash, Outbox},
+    persist::JsonStore,
+    proto::{storage_node_client::StorageNodeClient, ListBlobsRequest, NodeInfo, StoredBlob},
+    registry::NodeRegistry,
+    retrieve::{reconstruct_verified, retrieve_shards, CONNECT_TIMEOUT, STREAM_TIMEOUT},
+    sequencer::{
+        coding::{scheme_or_default, CodingAlgorithm, CodingScheme},
+        placement::place_missing_shards,
+        sequencer::{codec, encode_blob_with_proofs},
+    },
+    time::unix_now,
+};
+use alloy_eips::eip4844::Bytes48;
+use futures_util::future::join_all;
+use reth::primitives::{kzg::KzgSettings, B256};
+use reth_tracing::tracing::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{timeout, Instant};
+
+/// How often the storage network is audited for blobs that lost shards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepairPolicy {
+    /// Seconds between audits, 0 disables repair
+    pub interval_secs: u64,
+    /// Blobs with fewer healthy shards are repaired, all of their shards when unset
+    pub min_healthy_shards: Option<usize>,
+}
+
+impl Default for RepairPolicy {
+    fn default() -> Self {
+        Self { interval_secs: 300, min_healthy_shards: None }
+    }
+}
+
+/// A shard a storage node reported lost or corrupted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DamagedShard {
+    pub node_id: u32,
+    pub reason: String,
+    /// Unix time the shard was reported
+    pub reported_at: u64,
+}
+
+/// Shards waiting to be regenerated from the rest of their blob, by blob and shard index
+#[derive(Debug)]
+pub struct RepairQueue {
+    blobs: JsonStore<BTreeMap<String, BTreeMap<u32, DamagedShard>>>,
+}
+
+impl RepairQueue {
+    /// Load the queue from `path`, starting empty if the file does not exist yet
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self { blobs: JsonStore::load(path)? })
+    }
+
+    /// Queue a shard for repair, replacing an earlier report of it
+    pub fn add(
+        &mut self,
+        name: &str,
+        index: u32,
+        node_id: u32,
+        reason: String,
+    ) -> std::io::Result<()> {
+        let shard = DamagedShard { node_id, reason, reported_at: unix_now() };
+        self.blobs.update(|blobs| {
+            blobs.entry(name.to_string()).or_default().insert(index, shard);
+        })
+    }
+
+    /// Damaged shards of a blob, by shard index
+    pub fn get(&self, name: &str) -> Option<&BTreeMap<u32, DamagedShard>> {
+        self.blobs.get().get(name)
+    }
+
+    /// Blobs with shards waiting for repair
+    pub fn names(&self) -> Vec<String> {
+        self.blobs.get().keys().cloned().collect()
+    }
+
+    /// Forget the damaged shards of a blob, once it was repaired
+    pub fn remove(&mut self, name: &str) -> std::io::Result<()> {
+        if self.blobs.get().contains_key(name) {
+            self.blobs.update(|blobs| blobs.remove(name))?;
+        }
+        Ok(())
+    }
+
+    /// Number of shards waiting for repair
+    pub fn len(&self) -> usize {
+        self.blobs.get().values().map(|shards| shards.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.get().is_empty()
+    }
+}
+
+/// Shards of a blob held by the live storage nodes
+#[derive(Debug)]
+struct Holdings {
+    scheme: CodingScheme,
+    /// Copies of each shard, by shard index
+    shards: BTreeMap<u32, Vec<ShardCopy>>,
+}
+
+/// A node's copy of a shard
+#[derive(Debug, Clone, Copy)]
+struct ShardCopy {
+    node_id: u32,
+    /// SHA-256 the node stored the shard with, `None` when it did not say
+    checksum: Option<B256>,
+}
+
+impl Holdings {
+    /// Shard indices whose copies were stored with different checksums, so some are corrupt
+    fn conflicting(&self) -> Vec<u32> {
+        self.shards
+            .iter()
+            .filter(|(_, copies)| {
+                let mut checksums = copies.iter().filter_map(|copy| copy.checksum);
+                checksums.next().is_some_and(|first| checksums.any(|checksum| checksum != first))
+            })
+            .map(|(index, _)| *index)
+            .collect()
+    }
+
+    /// Shards of the blob each node holds, copies of the same shard included
+    fn load(&self) -> HashMap<u32, usize> {
+        let mut load = HashMap::new();
+        for copy in self.shards.values().flatten() {
+            *load.entry(copy.node_id).or_default() += 1;
+        }
+        load
+    }
+
+    fn holds_any(&self, node_id: u32) -> bool {
+        self.shards.values().flatten().any(|copy| copy.node_id == node_id)
+    }
+}
+
+/// Every blob a node lists, across the pages of its listing
+#[derive(Debug, Default)]
+struct Listing {
+    blobs: Vec<StoredBlob>,
+    /// Blobs the node deleted under its retention policy
+    expired: Vec<String>,
+}
+
+/// What the live storage nodes hold, gathered from their listings
+#[derive(Debug, Default)]
+struct Network {
+    holdings: BTreeMap<String, Holdings>,
+    /// Blobs any node deleted under its retention policy
+    expired: BTreeSet<String>,
+}
+
+impl Network {
+    fn add(&mut self, node_id: u32, listing: Listing) {
+        self.expired.extend(listing.expired);
+        for blob in listing.blobs {
+            let Ok(scheme) = scheme_or_default(blob.scheme.as_ref()) else { continue };
+            let holding = self
+                .holdings
+                .entry(blob.commitment)
+                .or_insert_with(|| Holdings { scheme, shards: BTreeMap::new() });
+            for (position, index) in blob.indices.into_iter().enumerate() {
+                let checksum = blob
+                    .checksums
+                    .get(position)
+                    .and_then(|checksum| B256::try_from(checksum.as_slice()).ok());
+                holding.shards.entry(index).or_default().push(ShardCopy { node_id, checksum });
+            }
+        }
+    }
+
+    /// What an audit makes of a blob, `reported` when a node reported its shards damaged
+    fn assess(&self, name: &str, policy: &RepairPolicy, reported: bool) -> Assessment {
+        let Some(holding) = self.holdings.get(name) else { return Assessment::Healthy };
+        // Peers keep a deleted blob until their own policy expires it, repairing it would only
+        // have the node delete it again
+        if self.expired.contains(name) {
+            return Assessment::Expired;
+        }
+        let total_shards = holding.scheme.total_shards();
+        let wanted = policy.min_healthy_shards.unwrap_or(total_shards).min(total_shards);
+        let healthy = holding.shards.len();
+        let conflicting = !holding.conflicting().is_empty();
+        if healthy == total_shards && !conflicting {
+            Assessment::Healthy
+        } else if healthy >= wanted && !conflicting && !reported {
+            Assessment::Tolerated
+        } else {
+            Assessment::Repair
+        }
+    }
+}
+
+/// What an audit makes of a blob
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assessment {
+    /// Every shard is held and no copies disagree
+    Healthy,
+    /// A node deleted it under its retention policy, so it is left to expire
+    Expired,
+    /// Lost shards, but no more than the policy tolerates
+    Tolerated,
+    Repair,
+}
+
+/// Regenerates the lost shards of blobs from the surviving ones and places them on live nodes
+///
+/// Every audit lists the blobs held by each live node. A blob with fewer healthy shards than the
+/// policy asks for, with shards reported damaged, or with copies of a shard that disagree, is
+/// retrieved from the nodes holding it, reconstructed, checked against its commitment and encoded
+/// again with the scheme it was stored with. Only the missing shards and the copies that differ
+/// from the regenerated ones are sent, through the outbox, so they are delivered until their node
+/// acknowledges them. Missing shards go to nodes holding fewer than `parity_shards` of the blob.
+/// Blobs a node lists as deleted under its retention policy are left to expire instead, and so
+/// are blobs being retracted or that were not distributed in a canonical block. Nodes that do
+/// not answer in time are left out of the audit like unreachable ones.
+pub struct RepairCoordinator {
+    policy: RepairPolicy,
+    registry: NodeRegistry,
+    queues: NodeQueues,
+    outbox: Arc<Mutex<Outbox>>,
+    distributed: Arc<Mutex<DistributedBlobs>>,
+    repairs: Arc<Mutex<RepairQueue>>,
+    kzg_settings: Arc<KzgSettings>,
+    trusted_setup: Option<PathBuf>,
+    /// Loaded on the first field element blob to repair
+    cell_setup: Mutex<Option<Arc<CellSetup>>>,
+}
+
+impl RepairCoordinator {
+    pub fn new(
+        policy: RepairPolicy,
+        registry: NodeRegistry,
+        queues: NodeQueues,
+        outbox: Arc<Mutex<Outbox>>,
+        distributed: Arc<Mutex<DistributedBlobs>>,
+        repairs: Arc<Mutex<RepairQueue>>,
+        trusted_setup: Option<PathBuf>,
+    ) -> Result<Self, TrustedSetupError> {
+        Ok(Self {
+            policy,
+            registry,
+            queues,
+            outbox,
+            distributed,
+            repairs,
+            kzg_settings: load_trusted_setup(trusted_setup.as_deref())?,
+            trusted_setup,
+            cell_setup: Mutex::default(),
+        })
+    }
+
+    /// Audit the storage network every interval of the policy
+    pub async fn run(self) {
+        if self.policy.interval_secs == 0 {
+            return;
+        }
+        // Nodes register again after the ExEx restarts, their shards must not look lost meanwhile
+        let period = Duration::from_secs(self.policy.interval_secs);
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            self.audit().await;
+        }
+    }
+
+    /// Repair every blob that lost too many shards or has shards reported damaged
+    pub async fn audit(&self) {
+        let nodes: Vec<NodeInfo> = self.registry.list().into_iter().filter(|n| n.live).collect();
+        let listings = join_all(nodes.iter().map(list_blobs)).await;
+
+        let mut reachable = Vec::with_capacity(nodes.len());
+        let mut network = Network::default();
+        for (node, listing) in nodes.iter().zip(listings) {
+            match listing {
+                Ok(listing) => network.add(node.node_id, listing),
+                Err(e) => {
+                    eprintln!("Failed to list the blobs of node {}: {:?}", node.node_id, e);
+                    continue;
+                }
+            }
+            reachable.push(node.clone());
+        }
+
+        let reported = self.repairs.lock().unwrap().names();
+        for (name, holding) in &network.holdings {
+            // Shards still being delivered are not lost, and shards being retracted are not wanted
+            let skipped = {
+                let outbox = self.outbox.lock().unwrap();
+                outbox.header(name).is_some() || outbox.is_retracting(name)
+            };
+            if skipped {
+                continue;
+            }
+            match network.assess(name, &self.policy, reported.contains(name)) {
+                // Reported shards may have been delivered again or expired in the meantime
+                Assessment::Healthy | Assessment::Expired => {
+                    if reported.contains(name) {
+                        self.forget(name);
+                    }
+                    continue;
+                }
+                Assessment::Tolerated => continue,
+                Assessment::Repair => {}
+            }
+
+            let conflicting = holding.conflicting();
+            if !conflicting.is_empty() {
+                info!("Blob {} has conflicting copies of shards {:?}", name, conflicting);
+            }
+            let total_shards = holding.scheme.total_shards();
+            info!(
+                "Blob {} has {} of {} shards, repairing",
+                name,
+                holding.shards.len(),
+                total_shards
+            );
+            match self.repair_blob(name, holding, &reachable).await {
+                Ok(Some(repaired)) => {
+                    info!("Sent {} regenerated shards of blob {}", repaired, name);
+                    self.forget(name);
+                }
+                Ok(None) => {
+                    info!("Blob {} was not distributed in a canonical block, leaving it", name);
+                    self.forget(name);
+                }
+                Err(e) => eprintln!("Failed to repair blob {}: {:?}", name, e),
+            }
+        }
+    }
+
+    /// Regenerate the shards of a blob missing from `holding`, returns how many were sent or
+    /// `None` when the blob does not belong to the canonical chain
+    async fn repair_blob(
+        &self,
+        name: &str,
+        holding: &Holdings,
+        nodes: &[NodeInfo],
+    ) -> eyre::Result<Option<usize>> {
+        let holders: Vec<NodeInfo> =
+            nodes.iter().filter(|node| holding.holds_any(node.node_id)).cloned().collect();
+        let candidates = retrieve_shards(holders, name).await?;
+        let commitment: Bytes48 = name.parse()?;
+        let (retrieved, data) = reconstruct_verified(candidates, commitment, &self.kzg_settings)?;
+        if !self.distributed.lock().unwrap().is_canonical(
+            name,
+            retrieved.block_number,
+            retrieved.block_hash,
+        ) {
+            return Ok(None);
+        }
+
+        let scheme = retrieved.scheme;
+        let cell_setup = match scheme.algorithm {
+            CodingAlgorithm::FieldElementFft => Some(self.cell_setup().await?),
+            CodingAlgorithm::ReedSolomonGf8 => None,
+        };
+        let codec = codec(&scheme)?;
+        let encoded = tokio::task::spawn_blocking(move || {
+            encode_blob_with_proofs(&codec, &data, commitment, &scheme, cell_setup.as_deref())
+        })
+        .await??;
+        // Regenerated shards must prove against the same root as the surviving ones
+        if let Some(root) = retrieved.merkle_root.filter(|root| *root != encoded.tree.root()) {
+            eyre::bail!("regenerated shards do not match Merkle root {}", root);
+        }
+
+        let node_ids: Vec<u32> = nodes
+            .iter()
+            .filter(|node| node.used_bytes < node.capacity)
+            .map(|node| node.node_id)
+            .collect();
+        let mut load = holding.load();
+        let missing: Vec<usize> = (0..scheme.total_shards())
+            .filter(|index| !holding.shards.contains_key(&(*index as u32)))
+            .collect();
+        let mut shards = place_missing_shards(
+            name,
+            &missing,
+            scheme.total_shards(),
+            scheme.parity_shards,
+            &node_ids,
+            &load,
+        )
+        .ok_or_else(|| eyre::eyre!("no live storage nodes with room left"))?;
+
+        // Copies that differ from the regenerated shard are overwritten where they are, one per
+        // shard per audit as the outbox follows a single node per shard
+        for (&index, copies) in &holding.shards {
+            let Some(shard) = encoded.shards.get(index as usize) else { continue };
+            let expected = shard_hash(shard);
+            if let Some(copy) =
+                copies.iter().find(|copy| copy.checksum.is_some_and(|c| c != expected))
+            {
+                eprintln!("Node {} holds a corrupt copy of shard {}", copy.node_id, index);
+                shards.push((index as usize, copy.node_id));
+                // Counted again once the outbox sends the overwriting copy
+                load.entry(copy.node_id).and_modify(|held| *held -= 1);
+            }
+        }
+        let header = BlobHeader {
+            name,
+            scheme,
+            block_number: retrieved.block_number,
+            block_hash: retrieved.block_hash,
+            sender: retrieved.sender,
+        };
+        // Adding writes every shard to disk, so it stays off the async workers
+        let outbox = self.outbox.clone();
+        let owned_name = name.to_string();
+        let BlobHeader { block_number, block_hash, sender, .. } = header;
+        let (encoded, shards) = tokio::task::spawn_blocking(move || {
+            let header = BlobHeader { name: &owned_name, scheme, block_number, block_hash, sender };
+            outbox.lock().unwrap().add_shards(&header, &encoded, &shards, &load)?;
+            Ok::<_, std::io::Error>((encoded, shards))
+        })
+        .await??;
+
+        let chunks = shards.iter().map(|&(index, node_id)| {
+            blob_chunk(
+                &header,
+                index as u32,
+                node_id,
+                encoded.shards[index].clone(),
+                encoded.proofs.get(index).copied(),
+                Some(&encoded.tree.proof(index)),
+            )
+        });
+        self.queues.send_chunks(name, chunks);
+        Ok(Some(shards.len()))
+    }
+
+    fn forget(&self, name: &str) {
+        if let Err(e) = self.repairs.lock().unwrap().remove(name) {
+            eprintln!("Failed to update the repair queue: {:?}", e);
+        }
+    }
+
+    async fn cell_setup(&self) -> eyre::Result<Arc<CellSetup>> {
+        if let Some(setup) = self.cell_setup.lock().unwrap().clone() {
+            return Ok(setup);
+        }
+        info!("Loading trusted setup to prove regenerated cells");
+        let path = self.trusted_setup.clone();
+        let setup = tokio::task::spawn_blocking(move || load_cell_setup(path.as_deref())).await??;
+        Ok(self.cell_setup.lock().unwrap().get_or_insert(setup).clone())
+    }
+}
+
+/// Every blob stored on a node, following the pages of its listing
+///
+/// Fails when the node takes longer than [`CONNECT_TIMEOUT`] to connect or [`STREAM_TIMEOUT`] to
+/// return a page, so a hanging node is treated as unreachable.
+async fn list_blobs(node: &NodeInfo) -> eyre::Result<Listing> {
+    let mut client = timeout(CONNECT_TIMEOUT, StorageNodeClient::connect(node.endpoint.clone()))
+        .await
+        .map_err(|_| eyre::eyre!("connecting timed out after {:?}", CONNECT_TIMEOUT))??;
+    let mut listing = Listing::default();
+    let mut page_token = String::new();
+    loop {
+        let request = ListBlobsRequest { page_token, page_size: 0 };
+        let response = timeout(STREAM_TIMEOUT, client.list_blobs(request))
+            .await
+            .map_err(|_| eyre::eyre!("listing blobs timed out after {:?}", STREAM_TIMEOUT))??
+            .into_inner();
+        listing.blobs.extend(response.blobs);
+        listing.expired.extend(response.expired);
+        if response.next_page_token.is_empty() {
+            return Ok(listing);
+        }
+        page_token = response.next_page_token;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        retention::{collect_garbage, RetentionPolicy, Tombstones},
+        storage::{list_blobs, FsEngine, ShardMeta, StorageEngine},
+    };
+
+    fn copy(node_id: u32, checksum: Option<u8>) -> ShardCopy {
+        ShardCopy { node_id, checksum: checksum.map(B256::repeat_byte) }
+    }
+
+    #[test]
+    fn finds_conflicting_copies_and_counts_them_per_node() {
+        let holding = Holdings {
+            scheme: CodingScheme::default(),
+            shards: BTreeMap::from([
+                (0, vec![copy(1, Some(1))]),
+                (1, vec![copy(1, Some(2)), copy(2, Some(2)), copy(3, None)]),
+                (2, vec![copy(2, Some(3)), copy(3, None), copy(1, Some(4))]),
+            ]),
+        };
+        assert_eq!(holding.conflicting(), vec![2]);
+        assert_eq!(holding.load(), HashMap::from([(1, 3), (2, 2), (3, 2)]));
+        assert!(holding.holds_any(3));
+        assert!(!holding.holds_any(4));
+    }
+
+    const SCHEME: CodingScheme = CodingScheme {
+        algorithm: CodingAlgorithm::ReedSolomonGf8,
+        data_shards: 2,
+        parity_shards: 2,
+    };
+
+    /// A storage node's store and tombstones, holding the given shards of each blob
+    fn node(dir: &Path, blobs: &[(&str, u64, &[u32])]) -> (FsEngine, Tombstones) {
+        let store = FsEngine::open(&dir.join("shards"), 1).unwrap();
+        for (name, block_number, indices) in blobs {
+            for index in *indices {
+                let shard = [*index as u8; 8];
+                let header = BlobHeader {
+                    name,
+                    scheme: SCHEME,
+                    block_number: *block_number,
+                    block_hash: B256::ZERO,
+                    sender: None,
+                };
+                let meta = ShardMeta::new(&header, *index, &shard, None);
+                store.put(&meta, &shard).unwrap();
+            }
+        }
+        (store, Tombstones::load(&dir.join("expired.json")).unwrap())
+    }
+
+    /// Collect garbage on a node the way it does on its own, recording what it deleted
+    fn collect(store: &FsEngine, tombstones: &mut Tombstones, policy: &RetentionPolicy) {
+        let report = collect_garbage(store, policy, 1_000, &Mutex::default()).unwrap();
+        tombstones.update(&report.removed, 1_000, policy.tombstone_secs * 1000).unwrap();
+    }
+
+    fn listing(store: &FsEngine, tombstones: &Tombstones) -> Listing {
+        let response = list_blobs(store, tombstones, "", 0).unwrap();
+        Listing { blobs: response.blobs, expired: response.expired }
+    }
+
+    #[test]
+    fn leaves_blobs_deleted_by_retention_to_expire() {
+        let (dir_a, dir_b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        // Node A stores a newer blob, so its block window moves past blob x before node B's does
+        let (store_a, mut tombstones_a) =
+            node(dir_a.path(), &[("x", 1, &[0, 1]), ("y", 100, &[0, 1, 2, 3])]);
+        let (store_b, mut tombstones_b) = node(dir_b.path(), &[("x", 1, &[2, 3])]);
+        let policy = RetentionPolicy { block_window: Some(10), ..Default::default() };
+        collect(&store_a, &mut tombstones_a, &policy);
+        collect(&store_b, &mut tombstones_b, &policy);
+        assert_eq!(store_a.indices("x").unwrap(), Vec::<u32>::new());
+        assert_eq!(store_b.indices("x").unwrap(), vec![2, 3]);
+
+        let mut network = Network::default();
+        network.add(1, listing(&store_a, &tombstones_a));
+        network.add(2, listing(&store_b, &tombstones_b));
+        let repair = RepairPolicy::default();
+        assert_eq!(network.assess("x", &repair, false), Assessment::Expired);
+        assert_eq!(network.assess("x", &repair, true), Assessment::Expired);
+        assert_eq!(network.assess("y", &repair, false), Assessment::Healthy);
+
+        // Without the tombstone the shards node A deleted look lost and would be sent back to it
+        network.expired.clear();
+        assert_eq!(network.assess("x", &repair, false), Assessment::Repair);
+    }
+
+    #[test]
+    fn repairs_blobs_missing_shards_nobody_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, tombstones) = node(dir.path(), &[("x", 1, &[0, 1, 2])]);
+        let mut network = Network::default();
+        network.add(1, listing(&store, &tombstones));
+
+        assert_eq!(network.assess("x", &RepairPolicy::default(), false), Assessment::Repair);
+        let tolerant = RepairPolicy { min_healthy_shards: Some(3), ..Default::default() };
+        assert_eq!(network.assess("x", &tolerant, false), Assessment::Tolerated);
+        assert_eq!(network.assess("x", &tolerant, true), Assessment::Repair);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    persist::JsonStore,
+    storage::{ShardMeta, StorageEngine},
+};
+use reth::primitives::Address;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Bound, path::Path, sync::Mutex};
+
+/// Which blobs a storage node keeps, every limit left unset keeps blobs forever
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Seconds between garbage collections, 0 disables them
+    pub interval_secs: u64,
+    /// Blobs first stored longer ago than this expire
+    pub max_age_secs: Option<u64>,
+    /// Blobs included more than this many blocks below the newest stored block expire
+    pub block_window: Option<u64>,
+    /// Bytes of shards kept at most, the oldest blobs are deleted beyond it
+    pub quota_bytes: Option<u64>,
+    /// Commitments of blobs that are never deleted
+    pub pinned_commitments: Vec<String>,
+    /// Senders whose blobs are never deleted
+    pub pinned_senders: Vec<Address>,
+    /// Seconds a deleted blob is listed as expired, so the ExEx does not repair it meanwhile
+    pub tombstone_secs: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            interval_secs: 600,
+            max_age_secs: None,
+            block_window: None,
+            quota_bytes: None,
+            pinned_commitments: Vec::new(),
+            pinned_senders: Vec::new(),
+            tombstone_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    fn is_pinned(&self, name: &str, sender: Option<Address>) -> bool {
+        self.pinned_commitments.iter().any(|pinned| pinned.eq_ignore_ascii_case(name))
+            || sender.is_some_and(|sender| self.pinned_senders.contains(&sender))
+    }
+}
+
+/// Outcome of a garbage collection
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    pub blobs_removed: u64,
+    pub shards_removed: u64,
+    /// Bytes of the deleted shards
+    pub bytes_freed: u64,
+    /// Names of the blobs deleted entirely
+    pub removed: Vec<String>,
+}
+
+/// What the store holds of one blob
+#[derive(Debug)]
+struct StoredBlob {
+    block_number: u64,
+    /// Unix time in milliseconds the first of its shards was stored at
+    stored_at_ms: u64,
+    sender: Option<Address>,
+    shards: Vec<ShardMeta>,
+    bytes: u64,
+}
+
+/// Delete every blob the policy no longer keeps, as of `now_ms`
+///
+/// Blobs expire by age or by block first, then the oldest are deleted until the rest fit in the
+/// quota. Pinned blobs are never deleted, and blobs of unknown block never expire by block. A
+/// node does not follow the chain, so the block window counts from the newest block it stores.
+///
+/// Only the shards found when the collection started are deleted, each while holding `writes`,
+/// which every write to the store must hold too, so a shard stored or replaced meanwhile is kept.
+pub fn collect_garbage(
+    store: &dyn StorageEngine,
+    policy: &RetentionPolicy,
+    now_ms: u64,
+    writes: &Mutex<()>,
+) -> std::io::Result<GcReport> {
+    let mut blobs: BTreeMap<String, StoredBlob> = BTreeMap::new();
+    for meta in store.shards_in_blocks(0..=u64::MAX)? {
+        let blob = blobs.entry(meta.commitment.clone()).or_insert(StoredBlob {
+            block_number: meta.block_number,
+            stored_at_ms: meta.received_at_ms,
+            sender: meta.sender,
+            shards: Vec::new(),
+            bytes: 0,
+        });
+        blob.block_number = blob.block_number.max(meta.block_number);
+        blob.stored_at_ms = blob.stored_at_ms.min(meta.received_at_ms);
+        blob.sender = blob.sender.or(meta.sender);
+        blob.bytes += meta.scheme.shard_size() as u64;
+        blob.shards.push(meta);
+    }
+    let newest_block = blobs.values().map(|blob| blob.block_number).max().unwrap_or_default();
+    // Pinned blobs count against the quota too, but only unpinned ones can make room
+    let mut pinned_bytes = 0;
+    blobs.retain(|name, blob| {
+        let pinned = policy.is_pinned(name, blob.sender);
+        if pinned {
+            pinned_bytes += blob.bytes;
+        }
+        !pinned
+    });
+
+    let expired = |blob: &StoredBlob| {
+        let too_old = policy.max_age_secs.is_some_and(|max_age| {
+            now_ms.saturating_sub(blob.stored_at_ms) > max_age.saturating_mul(1000)
+        });
+        let too_deep = policy.block_window.is_some_and(|window| {
+            blob.block_number != 0 && blob.block_number.saturating_add(window) < newest_block
+        });
+        too_old || too_deep
+    };
+    let (mut doomed, mut kept): (Vec<_>, Vec<_>) =
+        blobs.into_iter().partition(|(_, blob)| expired(blob));
+
+    if let Some(quota) = policy.quota_bytes {
+        let mut used = pinned_bytes + kept.iter().map(|(_, blob)| blob.bytes).sum::<u64>();
+        kept.sort_by_key(|(name, blob)| (blob.stored_at_ms, name.clone()));
+        let mut kept = kept.into_iter();
+        while used > quota {
+            let Some((name, blob)) = kept.next() else { break };
+            used -= blob.bytes;
+            doomed.push((name, blob));
+        }
+    }
+
+    let mut report = GcReport::default();
+    for (name, blob) in doomed {
+        for listed in blob.shards {
+            let _writing = writes.lock().unwrap();
+            if store.meta(&name, listed.index)?.as_ref() != Some(&listed) {
+                continue;
+            }
+            store.remove(&name, listed.index)?;
+            report.shards_removed += 1;
+            report.bytes_freed += listed.scheme.shard_size() as u64;
+        }
+        if store.indices(&name)?.is_empty() {
+            report.blobs_removed += 1;
+            report.removed.push(name);
+        }
+    }
+    Ok(report)
+}
+
+/// Blobs a node deleted under its retention policy, by name with the Unix time in milliseconds
+///
+/// Listed along with the stored blobs, so the ExEx leaves them to expire. Peers can hold on to
+/// their shards for longer, counting the block window from an older block or with room left in
+/// their quota, and the ExEx would otherwise take the deleted shards for lost and repair them.
+#[derive(Debug)]
+pub struct Tombstones {
+    blobs: JsonStore<BTreeMap<String, u64>>,
+}
+
+impl Tombstones {
+    /// Load the tombstones from `path`, starting empty if the file does not exist yet
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self { blobs: JsonStore::load(path)? })
+    }
+
+    /// Record the blobs a garbage collection deleted and forget those deleted over `ttl_ms` ago
+    pub fn update(&mut self, removed: &[String], now_ms: u64, ttl_ms: u64) -> std::io::Result<()> {
+        let blobs = self.blobs.get_mut();
+        let before = blobs.len();
+        blobs.retain(|_, deleted_at_ms| now_ms.saturating_sub(*deleted_at_ms) <= ttl_ms);
+        if removed.is_empty() && blobs.len() == before {
+            return Ok(());
+        }
+        for name in removed {
+            blobs.insert(name.clone(), now_ms);
+        }
+        self.blobs.save()
+    }
+
+    /// Names after `after` up to and including `through`, or all of them when it is `None`
+    pub fn range(&self, after: &str, through: Option<&str>) -> Vec<String> {
+        let end = through.map_or(Bound::Unbounded, Bound::Included);
+        self.blobs
+            .get()
+            .range::<str, _>((Bound::Excluded(after), end))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dispatch::BlobHeader,
+        sequencer::coding::{CodingAlgorithm, CodingScheme},
+        storage::FsEngine,
+    };
+    use reth::primitives::B256;
+    use std::{collections::BTreeSet, ops::RangeInclusive};
+
+    /// 65536 byte shards
+    const SCHEME: CodingScheme = CodingScheme {
+        algorithm: CodingAlgorithm::ReedSolomonGf8,
+        data_shards: 2,
+        parity_shards: 2,
+    };
+    const SHARD_BYTES: u64 = 65536;
+
+    /// Store shards 0 and 1 of a blob, as received at `received_at_ms`
+    fn put(store: &dyn StorageEngine, name: &str, block_number: u64, received_at_ms: u64) {
+        let sender = name.starts_with("pinned").then_some(Address::repeat_byte(1));
+        let header =
+            BlobHeader { name, scheme: SCHEME, block_number, block_hash: B256::ZERO, sender };
+        for index in 0..2 {
+            let shard = [index as u8; 8];
+            let mut meta = ShardMeta::new(&header, index, &shard, None);
+            meta.received_at_ms = received_at_ms;
+            store.put(&meta, &shard).unwrap();
+        }
+    }
+
+    fn stored(store: &dyn StorageEngine) -> BTreeSet<String> {
+        store.blobs().unwrap().into_keys().collect()
+    }
+
+    fn collect(store: &dyn StorageEngine, policy: &RetentionPolicy, now_ms: u64) -> GcReport {
+        collect_garbage(store, policy, now_ms, &Mutex::default()).unwrap()
+    }
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn expires_blobs_by_age_and_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsEngine::open(dir.path(), 1).unwrap();
+        put(&store, "old", 100, 1_000);
+        put(&store, "deep", 10, 50_000);
+        put(&store, "unknown_block", 0, 50_000);
+        put(&store, "new", 100, 50_000);
+
+        let policy = RetentionPolicy {
+            max_age_secs: Some(30),
+            block_window: Some(50),
+            ..RetentionPolicy::default()
+        };
+        let report = collect(&store, &policy, 60_000);
+        assert_eq!(report.removed, vec!["deep".to_string(), "old".to_string()]);
+        assert_eq!(report.blobs_removed, 2);
+        assert_eq!(report.shards_removed, 4);
+        assert_eq!(report.bytes_freed, 4 * SHARD_BYTES);
+        // Blobs of unknown block only expire by age
+        assert_eq!(stored(&store), names(&["new", "unknown_block"]));
+
+        let report = collect(&store, &policy, 90_000);
+        assert_eq!(report.removed, vec!["new".to_string(), "unknown_block".to_string()]);
+        assert!(stored(&store).is_empty());
+    }
+
+    #[test]
+    fn deletes_the_oldest_blobs_beyond_the_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsEngine::open(dir.path(), 1).unwrap();
+        put(&store, "b", 1, 2_000);
+        put(&store, "c", 1, 3_000);
+        put(&store, "a", 1, 1_000);
+        // Received at the same time as "c", ties go by name
+        put(&store, "d", 1, 3_000);
+
+        let policy = RetentionPolicy { quota_bytes: Some(5 * SHARD_BYTES), ..Default::default() };
+        let report = collect(&store, &policy, 4_000);
+        assert_eq!(report.removed, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(stored(&store), names(&["c", "d"]));
+
+        let policy = RetentionPolicy { quota_bytes: Some(2 * SHARD_BYTES), ..Default::default() };
+        collect(&store, &policy, 4_000);
+        assert_eq!(stored(&store), names(&["d"]));
+        assert!(collect(&store, &policy, 4_000).removed.is_empty());
+    }
+
+    #[test]
+    fn keeps_pinned_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsEngine::open(dir.path(), 1).unwrap();
+        put(&store, "pinned_by_sender", 1, 1_000);
+        put(&store, "0xPINNED", 1, 1_000);
+        put(&store, "old", 1, 2_000);
+        put(&store, "new", 1, 3_000);
+
+        let policy = RetentionPolicy {
+            max_age_secs: Some(1),
+            quota_bytes: Some(5 * SHARD_BYTES),
+            pinned_commitments: vec!["0xpinned".to_string()],
+            pinned_senders: vec![Address::repeat_byte(1)],
+            ..RetentionPolicy::default()
+        };
+        // Only "old" expired, but pinned blobs count against the quota so "new" has to go too
+        let report = collect(&store, &policy, 3_500);
+        assert_eq!(report.removed, vec!["old".to_string(), "new".to_string()]);
+        assert_eq!(stored(&store), names(&["0xPINNED", "pinned_by_sender"]));
+
+        // Pinned blobs stay even when they alone exceed the quota
+        let policy = RetentionPolicy { quota_bytes: Some(0), ..policy };
+        assert!(collect(&store, &policy, 100_000).removed.is_empty());
+        assert_eq!(stored(&store).len(), 2);
+    }
+
+    /// A store that has a shard stored right after garbage collection lists its shards
+    #[derive(Debug)]
+    struct StoresWhileListed {
+        store: FsEngine,
+        stored: (String, u32, u64),
+    }
+
+    impl StorageEngine for StoresWhileListed {
+        fn put(&self, meta: &ShardMeta, shard: &[u8]) -> std::io::Result<()> {
+            self.store.put(meta, shard)
+        }
+
+        fn get(&self, name: &str, index: u32) -> std::io::Result<Option<Vec<u8>>> {
+            self.store.get(name, index)
+        }
+
+        fn meta(&self, name: &str, index: u32) -> std::io::Result<Option<ShardMeta>> {
+            self.store.meta(name, index)
+        }
+
+        fn indices(&self, name: &str) -> std::io::Result<Vec<u32>> {
+            self.store.indices(name)
+        }
+
+        fn blobs(&self) -> std::io::Result<BTreeMap<String, Vec<u32>>> {
+            self.store.blobs()
+        }
+
+        fn blobs_after(
+            &self,
+            after: &str,
+            limit: usize,
+        ) -> std::io::Result<Vec<(String, Vec<u32>)>> {
+            self.store.blobs_after(after, limit)
+        }
+
+        fn shards_in_blocks(&self, blocks: RangeInclusive<u64>) -> std::io::Result<Vec<ShardMeta>> {
+            let listed = self.store.shards_in_blocks(blocks)?;
+            let (name, index, received_at_ms) = &self.stored;
+            let header = BlobHeader {
+                name,
+                scheme: SCHEME,
+                block_number: 1,
+                block_hash: B256::ZERO,
+                sender: None,
+            };
+            let shard = [0xff; 8];
+            let mut meta = ShardMeta::new(&header, *index, &shard, None);
+            meta.received_at_ms = *received_at_ms;
+            self.store.put(&meta, &shard)?;
+            Ok(listed)
+        }
+
+        fn remove(&self, name: &str, index: u32) -> std::io::Result<()> {
+            self.store.remove(name, index)
+        }
+
+        fn remove_blob(&self, name: &str) -> std::io::Result<()> {
+            self.store.remove_blob(name)
+        }
+
+        fn used_bytes(&self) -> u64 {
+            self.store.used_bytes()
+        }
+    }
+
+    #[test]
+    fn keeps_shards_stored_during_a_collection() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = RetentionPolicy { max_age_secs: Some(1), ..RetentionPolicy::default() };
+
+        // A shard the blob was missing, e.g. regenerated by repair
+        let store = FsEngine::open(&dir.path().join("added"), 1).unwrap();
+        put(&store, "a", 1, 1_000);
+        let store = StoresWhileListed { store, stored: ("a".to_string(), 2, 5_000) };
+        let report = collect(&store, &policy, 5_000);
+        assert_eq!(report.shards_removed, 2);
+        assert!(report.removed.is_empty());
+        assert_eq!(store.indices("a").unwrap(), vec![2]);
+
+        // A shard overwritten with a new copy
+        let store = FsEngine::open(&dir.path().join("replaced"), 1).unwrap();
+        put(&store, "a", 1, 1_000);
+        let store = StoresWhileListed { store, stored: ("a".to_string(), 1, 5_000) };
+        let report = collect(&store, &policy, 5_000);
+        assert_eq!(report.shards_removed, 1);
+        assert!(report.removed.is_empty());
+        assert_eq!(store.get("a", 1).unwrap(), Some(vec![0xff; 8]));
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{
+    merkle::MerkleProof,
+    outbox::shard_hash,
+    proto::{storage_node_client::StorageNodeClient, GetShardsRequest, NodeInfo, Shard},
+    sequencer::{
+        coding::{scheme_or_default, CodingScheme},
+        sequencer::reconstruct_blob,
+    },
+};
+use eyre::Result;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use reth::primitives::{
+    kzg::{Blob, KzgCommitment, KzgSettings},
+    Address, FixedBytes, B256,
+};
+use reth_tracing::tracing::info;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+use tokio::time::timeout;
+
+/// How long a storage node has to accept a connection
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a storage node has to stream every shard of a blob it holds
+pub(crate) const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shards of a blob collected from storage nodes that agree with each other, enough of them to
+/// reconstruct it
+#[derive(Debug)]
+pub struct RetrievedBlob {
+    pub scheme: CodingScheme,
+    /// Block the blob was included in, 0 when none of the nodes recorded it
+    pub block_number: u64,
+    pub block_hash: B256,
+    /// Address that sent the blob transaction, `None` when none of the nodes recorded it
+    pub sender: Option<Address>,
+    /// Root of the Merkle tree the shards were proven to be in, `None` for shards stored without one
+    pub merkle_root: Option<B256>,
+    pub shards: BTreeMap<usize, Vec<u8>>,
+}
+
+/// A shard returned by a storage node that passed its checks
+#[derive(Debug)]
+struct RetrievedShard {
+    index: usize,
+    data: Vec<u8>,
+    scheme: CodingScheme,
+    block_number: u64,
+    block_hash: B256,
+    sender: Option<Address>,
+    merkle_root: Option<B256>,
+}
+
+/// Shards agreeing on their scheme and Merkle root, with what their nodes recorded about the blob
+#[derive(Debug, Default)]
+struct Group {
+    block_number: u64,
+    block_hash: B256,
+    sender: Option<Address>,
+    shards: BTreeMap<usize, Vec<u8>>,
+}
+
+/// Collect the shards of a blob from `nodes`, returning every group of agreeing shards with enough
+/// of them to reconstruct it, the group with the most shards first
+///
+/// Nodes are asked at once, and the nodes still answering are dropped as soon as some group has
+/// enough shards. Shards are grouped by scheme and Merkle root, so shards of a forged tree are
+/// never decoded together with genuine ones. Which group is genuine is only known once
+/// reconstructed, see [`reconstruct_verified`].
+pub async fn retrieve_shards(
+    nodes: Vec<NodeInfo>,
+    commitment_hash: &str,
+) -> Result<Vec<RetrievedBlob>> {
+    if nodes.is_empty() {
+        eyre::bail!("No storage nodes to retrieve blob {} from", commitment_hash);
+    }
+    let mut requests: FuturesUnordered<_> = nodes
+        .iter()
+        .map(|node| async move {
+            (node.node_id, retrieve_shards_from_node(node, commitment_hash).await)
+        })
+        .collect();
+
+    let mut groups: HashMap<(CodingScheme, Option<B256>), Group> = HashMap::new();
+    while let Some((node_id, shards)) = requests.next().await {
+        let shards = match shards {
+            Ok(shards) => shards,
+            Err(e) => {
+                eprintln!("Error retrieving chunks from node {}: {:?}", node_id, e);
+                continue;
+            }
+        };
+        for shard in shards {
+            let key = (shard.scheme, shard.merkle_root);
+            if !groups.is_empty() && !groups.contains_key(&key) {
+                eprintln!(
+                    "Shard {} has a different scheme or Merkle root than earlier shards: {:?}",
+                    shard.index, key
+                );
+            }
+            let group = groups.entry(key).or_default();
+            if shard.block_number >= group.block_number {
+                group.block_number = shard.block_number;
+                group.block_hash = shard.block_hash;
+            }
+            group.sender = group.sender.or(shard.sender);
+            group.shards.insert(shard.index, shard.data);
+        }
+        if groups.iter().any(|((scheme, _), group)| group.shards.len() >= scheme.data_shards) {
+            break;
+        }
+    }
+    drop(requests);
+
+    let (complete, incomplete): (Vec<_>, Vec<_>) = groups
+        .into_iter()
+        .partition(|((scheme, _), group)| group.shards.len() >= scheme.data_shards);
+    if complete.is_empty() {
+        // Report on the group closest to complete
+        let Some(((scheme, _), Group { shards: all_chunks, .. })) =
+            incomplete.into_iter().max_by_key(|(_, group)| group.shards.len())
+        else {
+            eyre::bail!("No storage node holds shards of blob {}", commitment_hash);
+        };
+        let missing: Vec<usize> =
+            (0..scheme.total_shards()).filter(|index| !all_chunks.contains_key(index)).collect();
+        eyre::bail!(
+            "Only {} of the {} shards needed were retrieved, missing shards: {:?}",
+            all_chunks.len(),
+            scheme.data_shards,
+            missing
+        )
+    }
+
+    let mut candidates: Vec<RetrievedBlob> = complete
+        .into_iter()
+        .map(|((scheme, merkle_root), group)| RetrievedBlob {
+            scheme,
+            block_number: group.block_number,
+            block_hash: group.block_hash,
+            sender: group.sender,
+            merkle_root,
+            shards: group.shards,
+        })
+        .collect();
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.shards.len()));
+    info!("Collected {} groups of shards, enough to reconstruct", candidates.len());
+    Ok(candidates)
+}
+
+/// Reconstruct the candidates in order until one matches the commitment, returning it along with
+/// the blob
+pub fn reconstruct_verified(
+    candidates: Vec<RetrievedBlob>,
+    commitment: FixedBytes<48>,
+    kzg_settings: &KzgSettings,
+) -> Result<(RetrievedBlob, Vec<u8>)> {
+    let mut failure = None;
+    for candidate in candidates {
+        let verified = reconstruct_blob(&candidate.scheme, &candidate.shards)
+            .map_err(eyre::Report::from)
+            .and_then(|data| verify_commitment(&data, commitment, kzg_settings).map(|()| data));
+        match verified {
+            Ok(data) => return Ok((candidate, data)),
+            Err(e) => {
+                eprintln!(
+                    "Shards of Merkle root {:?} are not the blob: {}",
+                    candidate.merkle_root, e
+                );
+                failure = Some(e);
+            }
+        }
+    }
+    Err(failure.unwrap_or_else(|| eyre::eyre!("No shards to reconstruct blob {} from", commitment)))
+}
+
+/// Stream the stored shards of a blob from one node, keeping the valid ones
+async fn retrieve_shards_from_node(
+    node: &NodeInfo,
+    commitment_hash: &str,
+) -> Result<Vec<RetrievedShard>> {
+    let client = timeout(CONNECT_TIMEOUT, StorageNodeClient::connect(node.endpoint.clone()))
+        .await
+        .map_err(|_| eyre::eyre!("connecting timed out after {:?}", CONNECT_TIMEOUT))??;
+    let mut client = client.max_decoding_message_size(usize::MAX);
+
+    let stream = async {
+        let request = GetShardsRequest { commitment: commitment_hash.to_string(), indices: vec![] };
+        let mut response = client.get_shards(request).await?.into_inner();
+        let mut shards = Vec::new();
+        while let Some(shard) = response.message().await? {
+            shards.extend(check_shard(node.node_id, shard));
+        }
+        Ok::<_, eyre::Report>(shards)
+    };
+    timeout(STREAM_TIMEOUT, stream)
+        .await
+        .map_err(|_| eyre::eyre!("streaming shards timed out after {:?}", STREAM_TIMEOUT))?
+}
+
+/// A shard a node returned, if it is well formed and its hash and Merkle proof hold
+fn check_shard(node_id: u32, shard: Shard) -> Option<RetrievedShard> {
+    let index = shard.index as usize;
+    let Ok(scheme) = scheme_or_default(shard.scheme.as_ref()) else {
+        eprintln!("Node {} returned shard {} with an invalid scheme", node_id, index);
+        return None;
+    };
+    if index >= scheme.total_shards() || shard.data.len() != scheme.shard_size() {
+        eprintln!("Node {} returned malformed shard {}", node_id, index);
+        return None;
+    }
+    if B256::try_from(shard.hash.as_slice()).ok() != Some(shard_hash(&shard.data)) {
+        eprintln!("Node {} returned corrupted shard {}", node_id, index);
+        return None;
+    }
+    // Shards stored before Merkle proofs were sent have none, the commitment check covers them
+    let merkle_root = if shard.merkle_root.is_empty() {
+        None
+    } else {
+        match MerkleProof::from_bytes(&shard.merkle_root, &shard.merkle_branch) {
+            Some(merkle) if merkle.verify(&shard.data, index, scheme.total_shards()) => {
+                Some(merkle.root)
+            }
+            _ => {
+                eprintln!("Node {} returned shard {} failing its Merkle proof", node_id, index);
+                return None;
+            }
+        }
+    };
+    Some(RetrievedShard {
+        index,
+        data: shard.data,
+        scheme,
+        block_number: shard.block_number,
+        block_hash: B256::try_from(shard.block_hash.as_slice()).unwrap_or_default(),
+        sender: Address::try_from(shard.sender.as_slice()).ok(),
+        merkle_root,
+    })
+}
+
+/// Check that a reconstructed blob is the one the commitment was made to
+pub fn verify_commitment(
+    data: &[u8],
+    commitment: FixedBytes<48>,
+    kzg_settings: &KzgSettings,
+) -> Result<()> {
+    let blob = Blob::from_bytes(data).map_err(|e| eyre::eyre!("Invalid blob: {:?}", e))?;
+    let computed = KzgCommitment::blob_to_kzg_commitment(&blob, kzg_settings)
+        .map_err(|e| eyre::eyre!("Failed to compute commitment: {:?}", e))?;
+    if computed.to_bytes().into_inner() != commitment.0 {
+        eyre::bail!("Reconstructed blob does not match commitment {}", commitment);
+    }
+    info!("Reconstructed blob matches commitment {}", commitment);
+    Ok(())
+}
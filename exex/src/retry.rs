@@ -0,0 +1,147 @@
+use crate::{
+    persist::JsonStore,
+    time::{unix_now, unix_now_ms},
+};
+use rand::Rng;
+use reth::primitives::B256;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+/// How often and for how long fetching a block's sidecars is retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts before a block is given up on, including the first one
+    pub max_attempts: u32,
+    /// Backoff after the first failed attempt, doubled after every further failure
+    pub initial_backoff_ms: u64,
+    /// Upper bound of the backoff
+    pub max_backoff_ms: u64,
+    /// Time after which a block is given up on, regardless of attempts left
+    pub deadline_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 60_000,
+            deadline_secs: 3600,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before the next attempt, after `attempts` failed ones, with equal jitter
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(31);
+        let backoff =
+            self.initial_backoff_ms.saturating_mul(1 << exponent).min(self.max_backoff_ms);
+        let half = backoff / 2;
+        Duration::from_millis(half + rand::thread_rng().gen_range(0..=half))
+    }
+
+    /// Whether a block that failed `attempts` times since `first_seen` should be tried again
+    pub fn should_retry(&self, attempts: u32, first_seen: u64) -> bool {
+        attempts < self.max_attempts && unix_now().saturating_sub(first_seen) < self.deadline_secs
+    }
+}
+
+/// A block whose sidecars could not be fetched yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingBlock {
+    pub block_hash: B256,
+    pub attempts: u32,
+    /// Unix time of the first attempt
+    pub first_seen: u64,
+    /// Unix time in milliseconds of the next attempt
+    pub next_attempt_ms: u64,
+}
+
+/// Blocks waiting to be retried, persisted so they survive restarts
+#[derive(Debug)]
+pub struct PendingBlocks {
+    blocks: JsonStore<BTreeMap<u64, PendingBlock>>,
+}
+
+impl PendingBlocks {
+    /// Load the queue from `path`, starting empty if the file does not exist yet
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self { blocks: JsonStore::load(path)? })
+    }
+
+    /// Record a failed attempt for a block and schedule the next one
+    ///
+    /// Returns false, dropping the block from the queue, once the policy gives up on it.
+    pub fn record_failure(
+        &mut self,
+        block_number: u64,
+        block_hash: B256,
+        policy: &RetryPolicy,
+    ) -> std::io::Result<bool> {
+        self.postpone(block_number, block_hash, policy)?;
+        let pending = &self.blocks.get()[&block_number];
+        let retry = policy.should_retry(pending.attempts, pending.first_seen);
+        if !retry {
+            self.remove(block_number)?;
+        }
+        Ok(retry)
+    }
+
+    /// Record a failed attempt for a block and schedule the next one, however many failed before
+    pub fn postpone(
+        &mut self,
+        block_number: u64,
+        block_hash: B256,
+        policy: &RetryPolicy,
+    ) -> std::io::Result<()> {
+        let now = unix_now();
+        self.blocks.update(|blocks| {
+            let pending = blocks.entry(block_number).or_insert(PendingBlock {
+                block_hash,
+                attempts: 0,
+                first_seen: now,
+                next_attempt_ms: 0,
+            });
+            pending.attempts += 1;
+            pending.next_attempt_ms =
+                unix_now_ms() + policy.backoff(pending.attempts).as_millis() as u64;
+        })
+    }
+
+    pub fn remove(&mut self, block_number: u64) -> std::io::Result<Option<PendingBlock>> {
+        if !self.blocks.get().contains_key(&block_number) {
+            return Ok(None);
+        }
+        self.blocks.update(|blocks| blocks.remove(&block_number))
+    }
+
+    pub fn get(&self, block_number: u64) -> Option<&PendingBlock> {
+        self.blocks.get().get(&block_number)
+    }
+
+    /// Blocks whose next attempt is due
+    pub fn due(&self) -> Vec<(u64, PendingBlock)> {
+        let now = unix_now_ms();
+        self.blocks
+            .get()
+            .iter()
+            .filter(|(_, pending)| pending.next_attempt_ms <= now)
+            .map(|(number, pending)| (*number, pending.clone()))
+            .collect()
+    }
+
+    /// Lowest block number still waiting for its sidecars
+    pub fn lowest(&self) -> Option<u64> {
+        self.blocks.get().keys().next().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.get().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.get().is_empty()
+    }
+}
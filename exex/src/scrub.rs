@@ -0,0 +1,142 @@
+use crate::{
+    outbox::shard_hash,
+    persist::{write_atomic, write_json},
+    storage::{ShardMeta, StorageEngine},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{task::spawn_blocking, time::sleep};
+
+/// Why a stored shard failed its check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum Corruption {
+    #[error("shard is missing")]
+    Missing,
+    #[error("shard cannot be read")]
+    Unreadable,
+    #[error("checksum does not match")]
+    Checksum,
+    #[error("Merkle proof does not hold")]
+    Merkle,
+}
+
+/// A shard set aside by the scrubber
+#[derive(Debug, Clone)]
+pub struct CorruptShard {
+    pub commitment: String,
+    pub index: u32,
+    pub reason: Corruption,
+}
+
+/// Outcome of a pass over every stored shard
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    pub shards_checked: u64,
+    pub bytes_checked: u64,
+    pub corrupted: Vec<CorruptShard>,
+}
+
+/// Checks every stored shard against the checksum and Merkle proof it was received with
+///
+/// Corrupted shards are moved out of the store into the quarantine directory, so they are no
+/// longer served, and are left for the caller to report.
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    store: Arc<dyn StorageEngine>,
+    quarantine_dir: PathBuf,
+    /// Bytes read per second at most, unlimited when 0
+    rate: u64,
+}
+
+impl Scrubber {
+    pub fn new(store: Arc<dyn StorageEngine>, quarantine_dir: &Path, rate: u64) -> Self {
+        Self { store, quarantine_dir: quarantine_dir.to_path_buf(), rate }
+    }
+
+    /// Check every stored shard once, paced to the scrub rate
+    ///
+    /// Shards are read and hashed on the blocking thread pool, one at a time.
+    pub async fn scrub(&self) -> std::io::Result<ScrubReport> {
+        let started = Instant::now();
+        let mut report = ScrubReport::default();
+        let store = self.store.clone();
+        let blobs = spawn_blocking(move || store.blobs()).await??;
+        for (name, indices) in blobs {
+            for index in indices {
+                let scrubber = self.clone();
+                let blob = name.clone();
+                let Some((bytes, corrupted)) =
+                    spawn_blocking(move || scrubber.check(&blob, index)).await??
+                else {
+                    continue;
+                };
+                report.shards_checked += 1;
+                report.bytes_checked += bytes;
+                report.corrupted.extend(corrupted);
+
+                if self.rate > 0 {
+                    let due = report.bytes_checked as f64 / self.rate as f64;
+                    let elapsed = started.elapsed().as_secs_f64();
+                    if due > elapsed {
+                        sleep(Duration::from_secs_f64(due - elapsed)).await;
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Check a stored shard, quarantining it if corrupted, returns the bytes read and the
+    /// corruption found or `None` if the shard is gone
+    fn check(
+        &self,
+        name: &str,
+        index: u32,
+    ) -> std::io::Result<Option<(u64, Option<CorruptShard>)>> {
+        // The shard may have been retracted since the listing
+        let Some(meta) = self.store.meta(name, index)? else { return Ok(None) };
+        let (shard, checked) = match self.store.get(name, index) {
+            Ok(Some(shard)) => {
+                let checked = check_shard(&meta, &shard);
+                (Some(shard), checked)
+            }
+            Ok(None) => (None, Err(Corruption::Missing)),
+            Err(e) => {
+                eprintln!("Failed to read shard {} of blob {}: {:?}", index, name, e);
+                (None, Err(Corruption::Unreadable))
+            }
+        };
+        let bytes = shard.as_ref().map_or(0, |shard| shard.len() as u64);
+        let Err(reason) = checked else { return Ok(Some((bytes, None))) };
+        eprintln!("Shard {} of blob {} is corrupted: {}", index, name, reason);
+        self.quarantine(&meta, shard.as_deref())?;
+        Ok(Some((bytes, Some(CorruptShard { commitment: name.to_string(), index, reason }))))
+    }
+
+    /// Move a shard out of the store, keeping whatever is left of it for inspection
+    fn quarantine(&self, meta: &ShardMeta, shard: Option<&[u8]>) -> std::io::Result<()> {
+        let stem = format!("{}_{}", meta.commitment, meta.index);
+        if let Some(shard) = shard {
+            write_atomic(&self.quarantine_dir.join(format!("{}.bin", stem)), shard)?;
+        }
+        write_json(&self.quarantine_dir.join(format!("{}.json", stem)), meta)?;
+        self.store.remove(&meta.commitment, meta.index)
+    }
+}
+
+/// Check a shard against the metadata it was stored with
+pub fn check_shard(meta: &ShardMeta, shard: &[u8]) -> Result<(), Corruption> {
+    if shard_hash(shard) != meta.checksum {
+        return Err(Corruption::Checksum);
+    }
+    // The checksum was taken on receipt, the proof also catches metadata rotting along with it
+    if let Some(merkle) = &meta.merkle {
+        if !merkle.verify(shard, meta.index as usize, meta.scheme.total_shards()) {
+            return Err(Corruption::Merkle);
+        }
+    }
+    Ok(())
+}
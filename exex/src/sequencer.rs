@@ -1,2 +1,5 @@
+pub mod coding;
+pub mod fft;
+pub mod placement;
 pub mod sequencer;
 pub mod utils;
@@ -0,0 +1,161 @@
+use crate::proto;
+use serde::{Deserialize, Serialize};
+
+/// Bytes in a blob
+pub const BLOB_SIZE: usize = 131072;
+/// BLS12-381 scalar field elements in a blob
+pub const FIELD_ELEMENTS_PER_BLOB: usize = 4096;
+/// Bytes per big-endian field element
+pub const BYTES_PER_FIELD_ELEMENT: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodingError {
+    #[error("Coding scheme needs at least one data shard")]
+    NoDataShards,
+    #[error("Coding scheme has {0} shards, at most 256 are supported")]
+    TooManyShards(usize),
+    #[error("Unknown coding algorithm {0}")]
+    UnknownAlgorithm(i32),
+    #[error("Shard size {actual} does not match {expected} for {data_shards} data shards")]
+    ShardSize { data_shards: usize, expected: usize, actual: usize },
+    #[error("Field element coding needs a power of two from 64 to 4096 data shards, got {0}")]
+    FieldElementShards(usize),
+    #[error(
+        "Field element coding doubles the blob, {parity} parity shards for {data} data shards"
+    )]
+    ExtensionFactor { data: usize, parity: usize },
+}
+
+/// Erasure code shards are computed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodingAlgorithm {
+    /// Systematic Reed-Solomon over GF(2^8)
+    #[default]
+    ReedSolomonGf8,
+    /// The blob's polynomial over the BLS12-381 scalar field, evaluated on twice its domain
+    ///
+    /// Shards are cells of consecutive field elements, as in Danksharding, so each can be checked
+    /// against the blob's KZG commitment.
+    FieldElementFft,
+}
+
+/// How a blob is split into shards
+///
+/// Chosen per deployment and stored with every shard, so blobs encoded under an older scheme can
+/// still be reconstructed after it changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CodingScheme {
+    pub algorithm: CodingAlgorithm,
+    /// Shards holding the blob itself, any this many shards reconstruct it
+    pub data_shards: usize,
+    /// Redundant shards, how many shards of a blob can be lost
+    pub parity_shards: usize,
+}
+
+impl Default for CodingScheme {
+    fn default() -> Self {
+        Self { algorithm: CodingAlgorithm::ReedSolomonGf8, data_shards: 128, parity_shards: 32 }
+    }
+}
+
+impl CodingScheme {
+    pub fn validate(&self) -> Result<(), CodingError> {
+        if self.data_shards == 0 {
+            return Err(CodingError::NoDataShards);
+        }
+        match self.algorithm {
+            CodingAlgorithm::ReedSolomonGf8 if self.total_shards() > 256 => {
+                Err(CodingError::TooManyShards(self.total_shards()))
+            }
+            CodingAlgorithm::ReedSolomonGf8 => Ok(()),
+            CodingAlgorithm::FieldElementFft => {
+                // Cells must hold whole field elements and line up with the evaluation domain, and
+                // cell proofs need a G2 setup point per element of a cell, of which there are 65
+                if !self.data_shards.is_power_of_two()
+                    || !(64..=FIELD_ELEMENTS_PER_BLOB).contains(&self.data_shards)
+                {
+                    return Err(CodingError::FieldElementShards(self.data_shards));
+                }
+                if self.parity_shards != self.data_shards {
+                    return Err(CodingError::ExtensionFactor {
+                        data: self.data_shards,
+                        parity: self.parity_shards,
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn total_shards(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+
+    /// Bytes per shard, the last data shard is zero padded when the blob does not divide evenly
+    pub fn shard_size(&self) -> usize {
+        BLOB_SIZE.div_ceil(self.data_shards)
+    }
+}
+
+impl From<CodingAlgorithm> for proto::CodingAlgorithm {
+    fn from(algorithm: CodingAlgorithm) -> Self {
+        match algorithm {
+            CodingAlgorithm::ReedSolomonGf8 => proto::CodingAlgorithm::ReedSolomonGf8,
+            CodingAlgorithm::FieldElementFft => proto::CodingAlgorithm::FieldElementFft,
+        }
+    }
+}
+
+impl TryFrom<i32> for CodingAlgorithm {
+    type Error = CodingError;
+
+    fn try_from(algorithm: i32) -> Result<Self, Self::Error> {
+        match proto::CodingAlgorithm::try_from(algorithm) {
+            Ok(proto::CodingAlgorithm::ReedSolomonGf8) => Ok(CodingAlgorithm::ReedSolomonGf8),
+            Ok(proto::CodingAlgorithm::FieldElementFft) => Ok(CodingAlgorithm::FieldElementFft),
+            Err(_) => Err(CodingError::UnknownAlgorithm(algorithm)),
+        }
+    }
+}
+
+impl From<&CodingScheme> for proto::CodingScheme {
+    fn from(scheme: &CodingScheme) -> Self {
+        proto::CodingScheme {
+            algorithm: proto::CodingAlgorithm::from(scheme.algorithm) as i32,
+            data_shards: scheme.data_shards as u32,
+            parity_shards: scheme.parity_shards as u32,
+            shard_size: scheme.shard_size() as u32,
+        }
+    }
+}
+
+impl TryFrom<&proto::CodingScheme> for CodingScheme {
+    type Error = CodingError;
+
+    fn try_from(scheme: &proto::CodingScheme) -> Result<Self, Self::Error> {
+        let coding = CodingScheme {
+            algorithm: scheme.algorithm.try_into()?,
+            data_shards: scheme.data_shards as usize,
+            parity_shards: scheme.parity_shards as usize,
+        };
+        coding.validate()?;
+        if coding.shard_size() != scheme.shard_size as usize {
+            return Err(CodingError::ShardSize {
+                data_shards: coding.data_shards,
+                expected: coding.shard_size(),
+                actual: scheme.shard_size as usize,
+            });
+        }
+        Ok(coding)
+    }
+}
+
+/// Scheme of a shard received over the wire, shards sent before schemes were recorded used the
+/// default one
+pub fn scheme_or_default(
+    scheme: Option<&proto::CodingScheme>,
+) -> Result<CodingScheme, CodingError> {
+    scheme.map(CodingScheme::try_from).transpose().map(Option::unwrap_or_default)
+}
@@ -0,0 +1,247 @@
+use super::{
+    coding::{CodingScheme, BLOB_SIZE, BYTES_PER_FIELD_ELEMENT, FIELD_ELEMENTS_PER_BLOB},
+    sequencer::SequencerError,
+    utils::{be_bytes_to_field_elements, field_elements_to_be_bytes},
+};
+use ark_bls12_381::Fr;
+use ark_ff::{batch_inversion, FftField, Field, Zero};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use std::collections::BTreeMap;
+
+/// Erasure code over the BLS12-381 scalar field
+///
+/// A blob holds the evaluations of a polynomial of degree below 4096 over the 4096th roots of
+/// unity, in bit-reversed order. Encoding evaluates that polynomial over the 8192nd roots of unity,
+/// again in bit-reversed order, so the first half of the extended blob is the blob itself and any
+/// half of it determines the polynomial. Shards are cells of consecutive extended elements, which
+/// keeps every shard a set of openings of the blob's KZG commitment.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldElementCodec {
+    /// Field elements per shard
+    cell_size: usize,
+    data_shards: usize,
+    blob_domain: Radix2EvaluationDomain<Fr>,
+    extended_domain: Radix2EvaluationDomain<Fr>,
+}
+
+impl FieldElementCodec {
+    /// Codec of a validated field element scheme
+    pub fn new(scheme: &CodingScheme) -> Self {
+        Self {
+            cell_size: FIELD_ELEMENTS_PER_BLOB / scheme.data_shards,
+            data_shards: scheme.data_shards,
+            blob_domain: Radix2EvaluationDomain::new(FIELD_ELEMENTS_PER_BLOB)
+                .expect("the scalar field has a 4096 element domain"),
+            extended_domain: Radix2EvaluationDomain::new(2 * FIELD_ELEMENTS_PER_BLOB)
+                .expect("the scalar field has a 8192 element domain"),
+        }
+    }
+
+    /// Extend a blob to twice its size, shards are laid out back to back
+    pub fn encode(&self, blob: &[u8]) -> Result<Vec<u8>, SequencerError> {
+        if blob.len() != BLOB_SIZE {
+            return Err(SequencerError::BlobSize(blob.len()));
+        }
+        let elements = be_bytes_to_field_elements(blob)?;
+        let coefficients = self.blob_domain.ifft(&bit_reversed(&elements));
+        let extended = bit_reversed(&self.extended_domain.fft(&coefficients));
+        Ok(field_elements_to_be_bytes(&extended))
+    }
+
+    /// Recover a blob from any `data_shards` of its shards, keyed by shard index
+    ///
+    /// Fails when the shards do not all lie on one polynomial of the blob's degree.
+    pub fn reconstruct(
+        &self,
+        shards: &BTreeMap<usize, Vec<u8>>,
+    ) -> Result<Vec<u8>, SequencerError> {
+        let shard_size = self.cell_size * BYTES_PER_FIELD_ELEMENT;
+        if let Some((&index, _)) = shards
+            .iter()
+            .find(|(index, shard)| **index >= 2 * self.data_shards || shard.len() != shard_size)
+        {
+            return Err(SequencerError::Shard(index));
+        }
+
+        // The data shards are the blob itself
+        if (0..self.data_shards).all(|index| shards.contains_key(&index)) {
+            return Ok(shards.values().take(self.data_shards).flatten().copied().collect());
+        }
+        if shards.len() < self.data_shards {
+            return Err(SequencerError::NotEnoughShards {
+                available: shards.len(),
+                needed: self.data_shards,
+            });
+        }
+
+        // Evaluations over the extended domain in natural order, None where a shard is missing
+        let size = self.extended_domain.size();
+        let mut evaluations = vec![None; size];
+        for (&index, shard) in shards {
+            for (offset, element) in be_bytes_to_field_elements(shard)?.into_iter().enumerate() {
+                evaluations[reverse_bits(index * self.cell_size + offset, size)] = Some(element);
+            }
+        }
+
+        // With Z vanishing where evaluations are missing, E * Z is known everywhere, and the
+        // polynomial is (E * Z) / Z, divided on a coset where Z has no roots
+        let missing: Vec<Fr> = (0..size)
+            .filter(|i| evaluations[*i].is_none())
+            .map(|i| self.extended_domain.element(i))
+            .collect();
+        let vanishing = vanishing_polynomial(&missing);
+        let vanishing_evaluations = self.extended_domain.fft(&vanishing);
+        let product: Vec<Fr> = evaluations
+            .iter()
+            .zip(&vanishing_evaluations)
+            .map(|(evaluation, z)| evaluation.unwrap_or_else(Fr::zero) * z)
+            .collect();
+        let product = self.extended_domain.ifft(&product);
+
+        let offset = Fr::GENERATOR;
+        let product = self.extended_domain.fft(&scaled(&product, offset));
+        let mut divisor = self.extended_domain.fft(&scaled(&vanishing, offset));
+        batch_inversion(&mut divisor);
+        let quotient: Vec<Fr> = product.iter().zip(&divisor).map(|(p, z)| *p * z).collect();
+        let offset_inverse = offset.inverse().expect("the generator is not zero");
+        let coefficients = scaled(&self.extended_domain.ifft(&quotient), offset_inverse);
+
+        if coefficients[FIELD_ELEMENTS_PER_BLOB..].iter().any(|c| !c.is_zero()) {
+            return Err(SequencerError::InconsistentShards);
+        }
+        let elements = self.blob_domain.fft(&coefficients[..FIELD_ELEMENTS_PER_BLOB]);
+        Ok(field_elements_to_be_bytes(&bit_reversed(&elements)))
+    }
+}
+
+/// Reorder elements by bit-reversing their index, as blobs and cells are laid out
+pub(crate) fn bit_reversed<T: Copy>(elements: &[T]) -> Vec<T> {
+    (0..elements.len()).map(|i| elements[reverse_bits(i, elements.len())]).collect()
+}
+
+/// Reverse the bits of an index into a power of two sized domain
+pub(crate) fn reverse_bits(index: usize, size: usize) -> usize {
+    index.reverse_bits().checked_shr(usize::BITS - size.trailing_zeros()).unwrap_or(0)
+}
+
+/// Coefficients of the polynomial with exactly the given roots
+fn vanishing_polynomial(roots: &[Fr]) -> Vec<Fr> {
+    let mut coefficients = vec![Fr::ONE];
+    for root in roots {
+        coefficients.push(Fr::zero());
+        for i in (1..coefficients.len()).rev() {
+            coefficients[i] = coefficients[i - 1] - coefficients[i] * root;
+        }
+        coefficients[0] = -coefficients[0] * root;
+    }
+    coefficients
+}
+
+/// Coefficients of p(offset * x) given those of p(x)
+pub(crate) fn scaled(coefficients: &[Fr], offset: Fr) -> Vec<Fr> {
+    let mut power = Fr::ONE;
+    coefficients
+        .iter()
+        .map(|c| {
+            let term = *c * power;
+            power *= offset;
+            term
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::coding::CodingAlgorithm;
+    use ark_ff::{BigInteger, PrimeField, UniformRand};
+    use ark_std::test_rng;
+
+    const SCHEME: CodingScheme = CodingScheme {
+        algorithm: CodingAlgorithm::FieldElementFft,
+        data_shards: 64,
+        parity_shards: 64,
+    };
+
+    fn random_blob() -> Vec<u8> {
+        let mut rng = test_rng();
+        let elements: Vec<Fr> = (0..FIELD_ELEMENTS_PER_BLOB).map(|_| Fr::rand(&mut rng)).collect();
+        field_elements_to_be_bytes(&elements)
+    }
+
+    /// Shards of an extended blob whose index `keep` accepts
+    fn keep_shards(extended: &[u8], keep: impl Fn(usize) -> bool) -> BTreeMap<usize, Vec<u8>> {
+        extended
+            .chunks(SCHEME.shard_size())
+            .enumerate()
+            .filter(|(index, _)| keep(*index))
+            .map(|(index, shard)| (index, shard.to_vec()))
+            .collect()
+    }
+
+    fn assert_round_trip(keep: impl Fn(usize) -> bool) {
+        let codec = FieldElementCodec::new(&SCHEME);
+        let blob = random_blob();
+        let extended = codec.encode(&blob).unwrap();
+        assert_eq!(extended.len(), 2 * BLOB_SIZE);
+        assert_eq!(&extended[..BLOB_SIZE], &blob[..]);
+
+        let shards = keep_shards(&extended, keep);
+        assert_eq!(shards.len(), SCHEME.data_shards);
+        assert_eq!(codec.reconstruct(&shards).unwrap(), blob);
+    }
+
+    #[test]
+    fn reconstructs_without_parity_shards() {
+        assert_round_trip(|index| index < SCHEME.data_shards);
+    }
+
+    #[test]
+    fn reconstructs_without_data_shards() {
+        assert_round_trip(|index| index >= SCHEME.data_shards);
+    }
+
+    #[test]
+    fn reconstructs_from_a_mix_of_shards() {
+        assert_round_trip(|index| index % 2 == 1);
+    }
+
+    #[test]
+    fn rejects_too_few_shards() {
+        let codec = FieldElementCodec::new(&SCHEME);
+        let extended = codec.encode(&random_blob()).unwrap();
+        let shards = keep_shards(&extended, |index| index > SCHEME.data_shards);
+        assert!(matches!(
+            codec.reconstruct(&shards),
+            Err(SequencerError::NotEnoughShards { available: 63, needed: 64 })
+        ));
+    }
+
+    #[test]
+    fn rejects_shards_off_the_polynomial() {
+        let codec = FieldElementCodec::new(&SCHEME);
+        let extended = codec.encode(&random_blob()).unwrap();
+        let mut shards = keep_shards(&extended, |index| index >= SCHEME.data_shards - 1);
+        // One shard more than needed, with one of its elements changed
+        shards.get_mut(&SCHEME.data_shards).unwrap()[BYTES_PER_FIELD_ELEMENT - 1] ^= 1;
+        assert!(matches!(codec.reconstruct(&shards), Err(SequencerError::InconsistentShards)));
+    }
+
+    #[test]
+    fn rejects_non_canonical_field_elements() {
+        let modulus = Fr::MODULUS.to_bytes_be();
+        assert!(matches!(
+            be_bytes_to_field_elements(&modulus),
+            Err(SequencerError::FieldElement(0))
+        ));
+
+        let mut bytes = vec![0u8; BYTES_PER_FIELD_ELEMENT];
+        bytes.extend([0xff; BYTES_PER_FIELD_ELEMENT]);
+        assert!(matches!(be_bytes_to_field_elements(&bytes), Err(SequencerError::FieldElement(1))));
+
+        let mut below_modulus = Fr::MODULUS;
+        below_modulus.sub_with_borrow(&1u64.into());
+        let elements = be_bytes_to_field_elements(&below_modulus.to_bytes_be()).unwrap();
+        assert_eq!(elements, vec![-Fr::ONE]);
+    }
+}
@@ -0,0 +1,209 @@
+use sha2::{Digest, Sha256};
+use std::{cmp::Reverse, collections::HashMap};
+
+/// Storage node assigned to each shard of a blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// Node id per shard index
+    pub nodes: Vec<u32>,
+    /// Whether the blob can still be reconstructed after losing any single node
+    pub tolerates_node_loss: bool,
+}
+
+impl Placement {
+    /// Shard indices assigned to a node
+    pub fn shards_for(&self, node_id: u32) -> Vec<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| **node == node_id)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+/// Assign the shards of a blob to nodes with capacity-bounded rendezvous hashing
+///
+/// Every shard goes to the highest scoring node for `(name, shard index)` that still has room.
+/// A node holds at most `parity_shards` shards of a blob, so losing any one of them leaves enough
+/// shards to reconstruct it. When there are too few nodes for that, shards are spread evenly
+/// instead. Nodes come and go, so a blob's placement cannot be recomputed later from the current
+/// node set; readers ask the storage nodes which shards they hold.
+pub fn place_shards(
+    name: &str,
+    total_shards: usize,
+    parity_shards: usize,
+    nodes: &[u32],
+) -> Option<Placement> {
+    if nodes.is_empty() {
+        return None;
+    }
+    let tolerates_node_loss = nodes.len() * parity_shards >= total_shards;
+    let capacity =
+        if tolerates_node_loss { parity_shards } else { total_shards.div_ceil(nodes.len()) };
+
+    let mut load = vec![0usize; nodes.len()];
+    let mut assigned = Vec::with_capacity(total_shards);
+    for shard_index in 0..total_shards {
+        let (slot, node_id) = nodes
+            .iter()
+            .enumerate()
+            .filter(|(slot, _)| load[*slot] < capacity)
+            .max_by_key(|(_, node_id)| (score(name, shard_index, **node_id), **node_id))
+            .map(|(slot, node_id)| (slot, *node_id))
+            .expect("total capacity covers every shard");
+        load[slot] += 1;
+        assigned.push(node_id);
+    }
+
+    Some(Placement { nodes: assigned, tolerates_node_loss })
+}
+
+/// Assign the shards missing from a blob to nodes, given how many of its shards each one holds
+///
+/// Uses the scores of [`place_shards`] and skips nodes at the capacity it would give them, so a
+/// repaired blob still survives the loss of any node. When every node is full, the least loaded
+/// one takes the shard.
+pub fn place_missing_shards(
+    name: &str,
+    missing: &[usize],
+    total_shards: usize,
+    parity_shards: usize,
+    nodes: &[u32],
+    held: &HashMap<u32, usize>,
+) -> Option<Vec<(usize, u32)>> {
+    if nodes.is_empty() {
+        return None;
+    }
+    let capacity = if nodes.len() * parity_shards >= total_shards {
+        parity_shards
+    } else {
+        total_shards.div_ceil(nodes.len())
+    };
+
+    let mut load: Vec<usize> =
+        nodes.iter().map(|node_id| held.get(node_id).copied().unwrap_or_default()).collect();
+    let assigned = missing
+        .iter()
+        .map(|&shard_index| {
+            let scored = |slot: &usize| (score(name, shard_index, nodes[*slot]), nodes[*slot]);
+            let slot = (0..nodes.len())
+                .filter(|slot| load[*slot] < capacity)
+                .max_by_key(scored)
+                .or_else(|| {
+                    (0..nodes.len()).min_by_key(|slot| (load[*slot], Reverse(scored(slot))))
+                })
+                .expect("there is a node");
+            load[slot] += 1;
+            (shard_index, nodes[slot])
+        })
+        .collect();
+    Some(assigned)
+}
+
+fn score(name: &str, shard_index: usize, node_id: u32) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(name.as_bytes())
+        .chain_update((shard_index as u64).to_le_bytes())
+        .chain_update(node_id.to_le_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(placement: &Placement) -> HashMap<u32, usize> {
+        let mut load = HashMap::new();
+        for node_id in &placement.nodes {
+            *load.entry(*node_id).or_default() += 1;
+        }
+        load
+    }
+
+    #[test]
+    fn places_deterministically() {
+        let nodes: Vec<u32> = (1..=10).collect();
+        let placement = place_shards("blob", 160, 32, &nodes).unwrap();
+        assert_eq!(placement.nodes.len(), 160);
+        assert_eq!(place_shards("blob", 160, 32, &nodes), Some(placement.clone()));
+        // The order nodes are listed in does not matter
+        let reversed: Vec<u32> = nodes.iter().rev().copied().collect();
+        assert_eq!(place_shards("blob", 160, 32, &reversed), Some(placement.clone()));
+        assert_ne!(place_shards("other", 160, 32, &nodes), Some(placement));
+    }
+
+    #[test]
+    fn caps_shards_per_node_at_parity_shards() {
+        // Five nodes leave no slack, every node takes exactly `parity_shards`
+        for nodes in [vec![1, 2, 3, 4, 5], (1..=12).collect()] {
+            let placement = place_shards("blob", 160, 32, &nodes).unwrap();
+            assert!(placement.tolerates_node_loss);
+            assert!(load(&placement).values().all(|shards| *shards <= 32));
+        }
+    }
+
+    #[test]
+    fn spreads_evenly_over_too_few_nodes() {
+        let placement = place_shards("blob", 160, 32, &[1, 2, 3]).unwrap();
+        assert!(!placement.tolerates_node_loss);
+        let load = load(&placement);
+        assert_eq!(load.len(), 3);
+        assert!(load.values().all(|shards| *shards <= 54), "{load:?}");
+        assert_eq!(place_shards("blob", 160, 32, &[]), None);
+    }
+
+    #[test]
+    fn moves_few_shards_when_an_unrelated_node_comes_or_goes() {
+        let nodes: Vec<u32> = (1..=40).collect();
+        let placement = place_shards("blob", 12, 4, &nodes).unwrap();
+
+        // A node holding no shard leaving changes nothing
+        let idle = nodes.iter().copied().find(|node| !placement.nodes.contains(node)).unwrap();
+        let without: Vec<u32> = nodes.iter().copied().filter(|node| *node != idle).collect();
+        assert_eq!(place_shards("blob", 12, 4, &without), Some(placement.clone()));
+
+        // A node joining only takes shards for itself
+        let mut with = nodes.clone();
+        with.push(41);
+        let grown = place_shards("blob", 12, 4, &with).unwrap();
+        for (before, after) in placement.nodes.iter().zip(&grown.nodes) {
+            assert!(before == after || *after == 41);
+        }
+    }
+
+    #[test]
+    fn places_like_place_shards_when_nothing_is_held() {
+        let nodes = [1, 2, 3, 4, 5];
+        let placement = place_shards("blob", 12, 4, &nodes).unwrap();
+        let missing: Vec<usize> = (0..12).collect();
+        let assigned = place_missing_shards("blob", &missing, 12, 4, &nodes, &HashMap::new());
+        let expected: Vec<(usize, u32)> = placement.nodes.into_iter().enumerate().collect();
+        assert_eq!(assigned, Some(expected));
+    }
+
+    #[test]
+    fn skips_nodes_holding_parity_shards_already() {
+        let nodes = [1, 2, 3, 4];
+        // Node 1 holds as many shards as the blob can lose, node 2 one short of that
+        let held = HashMap::from([(1, 4), (2, 3), (3, 1)]);
+        let missing: Vec<usize> = (8..12).collect();
+        let assigned = place_missing_shards("blob", &missing, 12, 4, &nodes, &held).unwrap();
+
+        let mut load = held.clone();
+        for (_, node_id) in &assigned {
+            *load.entry(*node_id).or_default() += 1;
+        }
+        assert_eq!(load.get(&1), Some(&4));
+        assert!(load.values().all(|shards| *shards <= 4), "{load:?}");
+    }
+
+    #[test]
+    fn falls_back_to_the_least_loaded_node() {
+        let held = HashMap::from([(1, 6), (2, 9)]);
+        let assigned = place_missing_shards("blob", &[7, 8], 12, 4, &[1, 2], &held).unwrap();
+        assert_eq!(assigned.iter().map(|(_, node)| *node).collect::<Vec<_>>(), vec![1, 1]);
+        assert_eq!(place_missing_shards("blob", &[7], 12, 4, &[], &held), None);
+    }
+}
@@ -1,42 +1,211 @@
 use reed_solomon_erasure::galois_8::ReedSolomon;
 
+use super::{
+    coding::{CodingAlgorithm, CodingError, CodingScheme, BLOB_SIZE},
+    fft::FieldElementCodec,
+};
+use crate::{kzg::CellSetup, merkle::ShardTree};
+use alloy_eips::eip4844::Bytes48;
+use bytes::Bytes;
+use rayon::prelude::*;
 use reth::primitives::BlobTransactionSidecar;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, OnceLock},
+};
 
-const SHARD_SIZE: usize = 1024; // B
-const DATA_SHARDS: usize = 128; // Total number of shards
-const THRESHOLD: usize = 32; // Minimum number of shards required to reconstruct
-const BLOB_SIZE: usize = 131072; // Number of field elements in a blob
+#[derive(Debug, thiserror::Error)]
+pub enum SequencerError {
+    #[error("Invalid coding scheme: {0}")]
+    Scheme(#[from] CodingError),
 
+    #[error("Sidecar has {blobs} blobs but {commitments} commitments")]
+    CommitmentCount { blobs: usize, commitments: usize },
+
+    #[error("Blob is {0} bytes, expected {BLOB_SIZE}")]
+    BlobSize(usize),
+
+    #[error("Reed-Solomon encoding failed: {0:?}")]
+    Encode(reed_solomon_erasure::Error),
+
+    #[error("Reed-Solomon decoding failed: {0:?}")]
+    Decode(reed_solomon_erasure::Error),
+
+    #[error("Encoder task failed: {0}")]
+    Encoder(String),
+
+    #[error("Field element {0} is not in the BLS12-381 scalar field")]
+    FieldElement(usize),
+
+    #[error("Shard {0} is out of range or has the wrong size")]
+    Shard(usize),
+
+    #[error("Only {available} shards available, {needed} needed to reconstruct")]
+    NotEnoughShards { available: usize, needed: usize },
+
+    #[error("Shards do not belong to the same blob")]
+    InconsistentShards,
+}
+
+/// Encoder of a coding scheme
+#[derive(Debug)]
+pub enum Codec {
+    ReedSolomon(ReedSolomon),
+    FieldElement(FieldElementCodec),
+}
+
+/// A blob of a sidecar that could not be encoded
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to encode blob {commitment}: {source}")]
+pub struct BlobError {
+    pub commitment: Bytes48,
+    pub source: SequencerError,
+}
+
+/// Shards of a single blob, indexed from 0 within the blob
+///
+/// Every shard is a view into one buffer holding the whole encoded blob.
+#[derive(Debug, Clone)]
+pub struct EncodedBlob {
+    pub commitment: Bytes48,
+    pub shards: Vec<Bytes>,
+    /// KZG cell proof of every shard, empty unless the scheme codes field elements
+    pub proofs: Vec<Bytes48>,
+    /// Merkle tree over the shards, each is sent with its inclusion proof
+    pub tree: ShardTree,
+}
+
+/// Encode every blob of a sidecar separately, in sidecar order
+///
+/// Encoding runs on the blocking pool with blobs spread over the rayon pool, so it never holds up
+/// the runtime driving the node. A blob that fails to encode does not affect the others. Cell
+/// proofs are computed for field element schemes when a setup is given.
 pub async fn process_blob_sidecar(
     blob_sidecar: BlobTransactionSidecar,
-) -> eyre::Result<Vec<Vec<u8>>> {
+    scheme: &CodingScheme,
+    cell_setup: Option<Arc<CellSetup>>,
+) -> Result<Vec<Result<EncodedBlob, BlobError>>, SequencerError> {
     println!("Processing blob sidecar");
-    let mut all_shards = Vec::new();
-    for blob_in in blob_sidecar.blobs {
-        let blob_data: Vec<u8> = blob_in.to_vec();
+    scheme.validate()?;
+    if blob_sidecar.blobs.len() != blob_sidecar.commitments.len() {
+        return Err(SequencerError::CommitmentCount {
+            blobs: blob_sidecar.blobs.len(),
+            commitments: blob_sidecar.commitments.len(),
+        });
+    }
 
-        assert_eq!(blob_data.len(), BLOB_SIZE, "KZG blob must be exactly 131072 bytes");
+    let codec = codec(scheme)?;
+    let scheme = *scheme;
+    tokio::task::spawn_blocking(move || {
+        blob_sidecar
+            .blobs
+            .par_iter()
+            .zip(blob_sidecar.commitments.par_iter())
+            .map(|(blob, commitment)| {
+                encode_blob_with_proofs(
+                    &codec,
+                    blob.as_slice(),
+                    *commitment,
+                    &scheme,
+                    cell_setup.as_deref(),
+                )
+                .map_err(|source| BlobError { commitment: *commitment, source })
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| SequencerError::Encoder(e.to_string()))
+}
 
-        let data_shards = DATA_SHARDS;
-        let parity_shards = THRESHOLD;
-        let total_shards = data_shards + parity_shards;
+/// Encode a blob along with the proofs its shards are sent with
+pub fn encode_blob_with_proofs(
+    codec: &Codec,
+    blob_data: &[u8],
+    commitment: Bytes48,
+    scheme: &CodingScheme,
+    cell_setup: Option<&CellSetup>,
+) -> Result<EncodedBlob, SequencerError> {
+    let shards = encode_blob(codec, blob_data, scheme)?;
+    let proofs = match (cell_setup, scheme.algorithm) {
+        (Some(setup), CodingAlgorithm::FieldElementFft) => setup.prove_cells(blob_data, scheme)?,
+        _ => Vec::new(),
+    };
+    let tree = ShardTree::new(&shards);
+    Ok(EncodedBlob { commitment, shards, proofs, tree })
+}
 
-        let rs = ReedSolomon::new(data_shards, parity_shards)
-            .expect("Failed to initialize Reed-Solomon");
+/// Split a blob into the data and parity shards of a scheme
+pub fn encode_blob(
+    codec: &Codec,
+    blob_data: &[u8],
+    scheme: &CodingScheme,
+) -> Result<Vec<Bytes>, SequencerError> {
+    if blob_data.len() != BLOB_SIZE {
+        return Err(SequencerError::BlobSize(blob_data.len()));
+    }
 
-        let shard_size = blob_data.len() / data_shards;
-        assert_eq!(shard_size, SHARD_SIZE, "Shard size should be 1024 bytes");
+    // Shards are laid out back to back, data shards first
+    let shard_size = scheme.shard_size();
+    let buffer = match codec {
+        Codec::ReedSolomon(rs) => {
+            // Zero padded past the blob
+            let mut buffer = vec![0u8; shard_size * scheme.total_shards()];
+            buffer[..BLOB_SIZE].copy_from_slice(blob_data);
+            let mut shards: Vec<&mut [u8]> = buffer.chunks_exact_mut(shard_size).collect();
+            rs.encode(&mut shards).map_err(SequencerError::Encode)?;
+            buffer
+        }
+        Codec::FieldElement(codec) => codec.encode(blob_data)?,
+    };
+
+    let buffer = Bytes::from(buffer);
+    Ok((0..scheme.total_shards())
+        .map(|index| buffer.slice(index * shard_size..(index + 1) * shard_size))
+        .collect())
+}
 
-        let mut shards: Vec<Vec<u8>> = vec![vec![0u8; shard_size]; total_shards];
+/// Recover a blob from any `data_shards` of its shards, keyed by shard index
+pub fn reconstruct_blob(
+    scheme: &CodingScheme,
+    shards: &BTreeMap<usize, Vec<u8>>,
+) -> Result<Vec<u8>, SequencerError> {
+    match codec(scheme)?.as_ref() {
+        Codec::FieldElement(codec) => codec.reconstruct(shards),
+        Codec::ReedSolomon(rs) => {
+            let mut all_shards: Vec<Option<Vec<u8>>> = vec![None; scheme.total_shards()];
+            for (&index, shard) in shards {
+                if index >= scheme.total_shards() || shard.len() != scheme.shard_size() {
+                    return Err(SequencerError::Shard(index));
+                }
+                all_shards[index] = Some(shard.clone());
+            }
+            rs.reconstruct_data(&mut all_shards).map_err(SequencerError::Decode)?;
 
-        for (i, chunk) in blob_data.chunks(shard_size).enumerate().take(data_shards) {
-            shards[i].copy_from_slice(chunk);
+            // The last data shard may be padded
+            let mut data: Vec<u8> =
+                all_shards.into_iter().take(scheme.data_shards).flatten().flatten().collect();
+            data.truncate(BLOB_SIZE);
+            Ok(data)
         }
+    }
+}
 
-        rs.encode(&mut shards).expect("Failed to encode");
+/// Codec of a scheme, built once and shared afterwards
+pub fn codec(scheme: &CodingScheme) -> Result<Arc<Codec>, SequencerError> {
+    static CODECS: OnceLock<Mutex<HashMap<CodingScheme, Arc<Codec>>>> = OnceLock::new();
 
-        all_shards.extend(shards);
+    let mut codecs = CODECS.get_or_init(Default::default).lock().unwrap();
+    if let Some(codec) = codecs.get(scheme) {
+        return Ok(codec.clone());
     }
-
-    Ok(all_shards)
+    scheme.validate()?;
+    let codec = Arc::new(match scheme.algorithm {
+        CodingAlgorithm::ReedSolomonGf8 => Codec::ReedSolomon(
+            ReedSolomon::new(scheme.data_shards, scheme.parity_shards)
+                .map_err(SequencerError::Encode)?,
+        ),
+        CodingAlgorithm::FieldElementFft => Codec::FieldElement(FieldElementCodec::new(scheme)),
+    });
+    codecs.insert(*scheme, codec.clone());
+    Ok(codec)
 }
@@ -1,5 +1,7 @@
+use super::{coding::BYTES_PER_FIELD_ELEMENT, sequencer::SequencerError};
 use alloy::hex;
 use ark_bls12_381::{Bls12_381, Fr};
+use ark_ff::{BigInteger, PrimeField};
 use ark_poly_commit::kzg10::Commitment;
 use ark_serialize::CanonicalDeserialize;
 use eyre::Result;
@@ -44,3 +46,24 @@ pub fn fixedbytes_to_field_elements<const N: usize>(
 
     Ok(field_elements)
 }
+
+/// Parse big-endian field elements as laid out in a blob
+///
+/// Fails with the index of the first element that is not in the field.
+pub fn be_bytes_to_field_elements(bytes: &[u8]) -> Result<Vec<Fr>, SequencerError> {
+    bytes
+        .chunks(BYTES_PER_FIELD_ELEMENT)
+        .enumerate()
+        .map(|(index, chunk)| {
+            // Arkworks reads little-endian and rejects non-canonical values
+            let mut le = chunk.to_vec();
+            le.reverse();
+            Fr::deserialize_compressed(&le[..]).map_err(|_| SequencerError::FieldElement(index))
+        })
+        .collect()
+}
+
+/// Serialize field elements as big-endian bytes, the inverse of [`be_bytes_to_field_elements`]
+pub fn field_elements_to_be_bytes(elements: &[Fr]) -> Vec<u8> {
+    elements.iter().flat_map(|element| element.into_bigint().to_bytes_be()).collect()
+}
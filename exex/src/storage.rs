@@ -0,0 +1,358 @@
+mod fs;
+mod kv;
+
+pub use fs::FsEngine;
+pub use kv::KvEngine;
+
+use crate::{
+    dispatch::BlobHeader,
+    merkle::MerkleProof,
+    outbox::shard_hash,
+    proto::{ListBlobsResponse, StoredBlob},
+    retention::Tombstones,
+    sequencer::coding::CodingScheme,
+    time::unix_now_ms,
+};
+use reth::primitives::{Address, B256};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::RangeInclusive, path::Path, sync::Arc};
+
+/// Blobs per `ListBlobs` page when the request does not say
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// How a storage node keeps its shards on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineKind {
+    /// A file per shard and per shard's metadata in the storage directory
+    #[default]
+    Fs,
+    /// An embedded sled database in the storage directory
+    Sled,
+}
+
+/// What a storage node records about every shard it stores
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardMeta {
+    /// Name of the blob, its KZG commitment
+    pub commitment: String,
+    pub index: u32,
+    /// Block the blob was included in, 0 when the ExEx did not say
+    pub block_number: u64,
+    /// Hash of that block, retractions of the blob from other blocks leave the shard alone
+    pub block_hash: B256,
+    pub scheme: CodingScheme,
+    /// Unix time in milliseconds the shard was stored at
+    pub received_at_ms: u64,
+    /// SHA-256 of the shard as received
+    pub checksum: B256,
+    /// Inclusion proof in the blob's shard tree the shard was received with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle: Option<MerkleProof>,
+    /// Address that sent the blob transaction, `None` when the ExEx did not say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<Address>,
+}
+
+impl ShardMeta {
+    /// Metadata of a shard of the blob of `header` received just now
+    pub fn new(
+        header: &BlobHeader<'_>,
+        index: u32,
+        shard: &[u8],
+        merkle: Option<MerkleProof>,
+    ) -> Self {
+        Self {
+            commitment: header.name.to_string(),
+            index,
+            block_number: header.block_number,
+            block_hash: header.block_hash,
+            scheme: header.scheme,
+            received_at_ms: unix_now_ms(),
+            checksum: shard_hash(shard),
+            merkle,
+            sender: header.sender,
+        }
+    }
+}
+
+/// Durable store of the shards held by a storage node, with their metadata
+///
+/// Once `put` returns, a shard and its metadata survive a crash. A crash during `put` leaves the
+/// shard either stored with its metadata or not at all once the store is opened again, but not
+/// every engine writes both at once: see each engine for how it reconciles them on open.
+pub trait StorageEngine: Send + Sync + std::fmt::Debug {
+    /// Store a shard, replacing any earlier copy
+    fn put(&self, meta: &ShardMeta, shard: &[u8]) -> std::io::Result<()>;
+
+    /// Read a shard, `None` if it is not stored here
+    fn get(&self, name: &str, index: u32) -> std::io::Result<Option<Vec<u8>>>;
+
+    /// Metadata of a shard, `None` if it is not stored here
+    fn meta(&self, name: &str, index: u32) -> std::io::Result<Option<ShardMeta>>;
+
+    /// Indices of the stored shards of a blob, in ascending order
+    fn indices(&self, name: &str) -> std::io::Result<Vec<u32>>;
+
+    /// Every stored blob with the indices of its shards, ordered by name
+    fn blobs(&self) -> std::io::Result<BTreeMap<String, Vec<u32>>>;
+
+    /// Up to `limit` stored blobs named after `after`, with the indices of their shards, ordered
+    /// by name
+    fn blobs_after(&self, after: &str, limit: usize) -> std::io::Result<Vec<(String, Vec<u32>)>>;
+
+    /// Metadata of the stored shards of blobs included in `blocks`, ordered by block
+    fn shards_in_blocks(&self, blocks: RangeInclusive<u64>) -> std::io::Result<Vec<ShardMeta>>;
+
+    /// Delete a stored shard
+    fn remove(&self, name: &str, index: u32) -> std::io::Result<()>;
+
+    /// Delete every stored shard of a blob
+    fn remove_blob(&self, name: &str) -> std::io::Result<()>;
+
+    /// Bytes taken up on disk
+    fn used_bytes(&self) -> u64;
+}
+
+/// Open the store of a storage node in `dir`, recovering from any interrupted write
+pub fn open_engine(
+    kind: EngineKind,
+    dir: &Path,
+    node_id: u32,
+) -> std::io::Result<Arc<dyn StorageEngine>> {
+    Ok(match kind {
+        EngineKind::Fs => Arc::new(FsEngine::open(dir, node_id)?),
+        EngineKind::Sled => Arc::new(KvEngine::open(dir)?),
+    })
+}
+
+/// Delete the stored shards of a blob that were included in the block `block_hash`, returning how
+/// many were deleted
+///
+/// Shards of the blob included in another block, e.g. the one it was mined in again after a reorg,
+/// are kept.
+pub fn retract_blob_shards(
+    store: &dyn StorageEngine,
+    name: &str,
+    block_hash: B256,
+) -> std::io::Result<usize> {
+    let mut removed = 0;
+    for index in store.indices(name)? {
+        if store.meta(name, index)?.is_some_and(|meta| meta.block_hash == block_hash) {
+            store.remove(name, index)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// A page of the stored blobs after `page_token`, with the blobs deleted from its range
+///
+/// Blobs are ordered by name, a page starts after the last name of the previous one.
+pub fn list_blobs(
+    store: &dyn StorageEngine,
+    tombstones: &Tombstones,
+    page_token: &str,
+    page_size: usize,
+) -> std::io::Result<ListBlobsResponse> {
+    let page_size = match page_size {
+        0 => DEFAULT_PAGE_SIZE,
+        page_size => page_size.min(MAX_PAGE_SIZE),
+    };
+    let mut blobs: Vec<StoredBlob> = store
+        .blobs_after(page_token, page_size + 1)?
+        .into_iter()
+        .map(|(commitment, indices)| {
+            let metas = indices
+                .iter()
+                .map(|index| store.meta(&commitment, *index))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            let scheme = metas.iter().flatten().next().map(|meta| (&meta.scheme).into());
+            // Empty for a shard removed since the listing
+            let checksums = metas
+                .iter()
+                .map(|meta| meta.as_ref().map(|meta| meta.checksum.to_vec()).unwrap_or_default())
+                .collect();
+            Ok(StoredBlob { commitment, indices, scheme, checksums })
+        })
+        .collect::<std::io::Result<_>>()?;
+    let next_page_token = if blobs.len() > page_size {
+        blobs.truncate(page_size);
+        blobs.last().map(|blob| blob.commitment.clone()).unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let through = Some(next_page_token.as_str()).filter(|token| !token.is_empty());
+    let expired = tombstones.range(page_token, through);
+    Ok(ListBlobsResponse { blobs, next_page_token, expired })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store of every engine, each in a directory of its own
+    fn engines(dir: &Path) -> Vec<(EngineKind, Arc<dyn StorageEngine>)> {
+        [EngineKind::Fs, EngineKind::Sled]
+            .into_iter()
+            .map(|kind| (kind, open_engine(kind, &dir.join(format!("{kind:?}")), 1).unwrap()))
+            .collect()
+    }
+
+    /// Open a store again once its lock is free, sled releases it from a background thread
+    fn reopen(kind: EngineKind, dir: &Path) -> Arc<dyn StorageEngine> {
+        for _ in 0..100 {
+            if let Ok(store) = open_engine(kind, dir, 1) {
+                return store;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        open_engine(kind, dir, 1).unwrap()
+    }
+
+    fn put_in_block(store: &dyn StorageEngine, name: &str, index: u32, block_number: u64) {
+        let header = BlobHeader {
+            name,
+            scheme: CodingScheme::default(),
+            block_number,
+            block_hash: B256::with_last_byte(block_number as u8),
+            sender: None,
+        };
+        let shard = [index as u8 + block_number as u8; 8];
+        store.put(&ShardMeta::new(&header, index, &shard, None), &shard).unwrap();
+    }
+
+    fn put(store: &dyn StorageEngine, name: &str, index: u32, block_hash: B256) {
+        let header = BlobHeader {
+            name,
+            scheme: CodingScheme::default(),
+            block_number: 1,
+            block_hash,
+            sender: None,
+        };
+        let shard = [index as u8; 8];
+        store.put(&ShardMeta::new(&header, index, &shard, None), &shard).unwrap();
+    }
+
+    fn shard_keys(metas: Vec<ShardMeta>) -> Vec<(u64, String, u32)> {
+        metas.into_iter().map(|meta| (meta.block_number, meta.commitment, meta.index)).collect()
+    }
+
+    #[test]
+    fn stores_replaces_and_removes_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        for (kind, store) in engines(dir.path()) {
+            put_in_block(&*store, "a", 0, 1);
+            put_in_block(&*store, "a", 1, 1);
+            put_in_block(&*store, "b", 0, 2);
+            assert_eq!(store.get("a", 1).unwrap(), Some(vec![2; 8]), "{kind:?}");
+            assert_eq!(store.meta("b", 0).unwrap().map(|meta| meta.block_number), Some(2));
+            assert_eq!(store.get("a", 2).unwrap(), None);
+            assert_eq!(store.meta("c", 0).unwrap(), None);
+
+            // A shard stored again from another block is only listed under the new one
+            put_in_block(&*store, "a", 1, 3);
+            assert_eq!(store.get("a", 1).unwrap(), Some(vec![4; 8]), "{kind:?}");
+            assert_eq!(
+                shard_keys(store.shards_in_blocks(0..=9).unwrap()),
+                vec![(1, "a".into(), 0), (2, "b".into(), 0), (3, "a".into(), 1)],
+                "{kind:?}"
+            );
+
+            store.remove("a", 0).unwrap();
+            assert_eq!(store.indices("a").unwrap(), vec![1], "{kind:?}");
+            store.remove_blob("b").unwrap();
+            assert_eq!(store.get("b", 0).unwrap(), None);
+            let blobs = store.blobs().unwrap();
+            assert_eq!(blobs, BTreeMap::from([("a".to_string(), vec![1])]), "{kind:?}");
+            assert_eq!(
+                shard_keys(store.shards_in_blocks(0..=9).unwrap()),
+                vec![(3, "a".into(), 1)]
+            );
+            store.remove("a", 7).unwrap();
+        }
+    }
+
+    #[test]
+    fn lists_shards_by_block() {
+        let dir = tempfile::tempdir().unwrap();
+        for (kind, store) in engines(dir.path()) {
+            put_in_block(&*store, "b", 1, 5);
+            put_in_block(&*store, "a", 0, 5);
+            put_in_block(&*store, "c", 0, 3);
+            put_in_block(&*store, "d", 0, 300);
+            assert_eq!(
+                shard_keys(store.shards_in_blocks(4..=5).unwrap()),
+                vec![(5, "a".into(), 0), (5, "b".into(), 1)],
+                "{kind:?}"
+            );
+            assert_eq!(
+                shard_keys(store.shards_in_blocks(0..=3).unwrap()),
+                vec![(3, "c".into(), 0)]
+            );
+            assert_eq!(store.shards_in_blocks(6..=299).unwrap(), Vec::new(), "{kind:?}");
+            assert_eq!(store.shards_in_blocks(300..=u64::MAX).unwrap().len(), 1, "{kind:?}");
+            #[allow(clippy::reversed_empty_ranges)]
+            let empty = store.shards_in_blocks(5..=4).unwrap();
+            assert!(empty.is_empty(), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn pages_through_blobs_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        for (kind, store) in engines(dir.path()) {
+            for (name, index) in [("a", 0), ("a", 2), ("ab", 1), ("b", 0), ("c", 0)] {
+                put_in_block(&*store, name, index, 1);
+            }
+            let names = |page: Vec<(String, Vec<u32>)>| -> Vec<String> {
+                page.into_iter().map(|(name, _)| name).collect()
+            };
+            let first = store.blobs_after("", 2).unwrap();
+            assert_eq!(first, vec![("a".into(), vec![0, 2]), ("ab".into(), vec![1])], "{kind:?}");
+            // A name that another one starts with is not mistaken for it
+            assert_eq!(names(store.blobs_after("a", 2).unwrap()), vec!["ab", "b"], "{kind:?}");
+            assert_eq!(names(store.blobs_after("ab", 10).unwrap()), vec!["b", "c"], "{kind:?}");
+            assert_eq!(names(store.blobs_after("bb", 10).unwrap()), vec!["c"], "{kind:?}");
+            assert!(store.blobs_after("c", 10).unwrap().is_empty(), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_shards_across_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        for (kind, store) in engines(dir.path()) {
+            put_in_block(&*store, "a", 0, 1);
+            put_in_block(&*store, "a", 1, 2);
+            store.remove("a", 0).unwrap();
+            let meta = store.meta("a", 1).unwrap();
+            drop(store);
+
+            let store = reopen(kind, &dir.path().join(format!("{kind:?}")));
+            assert_eq!(store.indices("a").unwrap(), vec![1], "{kind:?}");
+            assert_eq!(store.get("a", 1).unwrap(), Some(vec![3; 8]), "{kind:?}");
+            assert_eq!(store.meta("a", 1).unwrap(), meta, "{kind:?}");
+            assert_eq!(
+                shard_keys(store.shards_in_blocks(0..=9).unwrap()),
+                vec![(2, "a".into(), 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn retracts_only_shards_of_the_orphaned_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsEngine::open(dir.path(), 1).unwrap();
+        let (orphaned, canonical) = (B256::repeat_byte(1), B256::repeat_byte(2));
+        put(&store, "a", 0, orphaned);
+        put(&store, "a", 1, orphaned);
+        put(&store, "a", 2, canonical);
+        put(&store, "b", 0, orphaned);
+
+        assert_eq!(retract_blob_shards(&store, "a", orphaned).unwrap(), 2);
+        assert_eq!(store.indices("a").unwrap(), vec![2]);
+        assert_eq!(store.indices("b").unwrap(), vec![0]);
+        assert_eq!(retract_blob_shards(&store, "a", orphaned).unwrap(), 0);
+    }
+}
@@ -0,0 +1,367 @@
+use super::{ShardMeta, StorageEngine};
+use crate::persist::{remove_if_exists, write_atomic};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Bound, RangeInclusive},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+/// Where shards with unreadable metadata are moved, shared with the scrubber
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Shards as files in the storage directory, indexed in memory
+///
+/// Every shard has a `chunk_` file with its bytes and a `meta_` file with its metadata, each written
+/// atomically. Storing a shard removes the metadata of an earlier copy first and writes the new
+/// metadata last, so a shard without metadata was interrupted while being stored, was never
+/// acknowledged, and is dropped when the store is opened again. Metadata that cannot be parsed is
+/// moved to the quarantine directory along with its shard.
+#[derive(Debug)]
+pub struct FsEngine {
+    dir: PathBuf,
+    node_id: u32,
+    index: RwLock<Index>,
+    /// Size of the files in the directory, kept up to date by every write and removal
+    used_bytes: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    blobs: BTreeMap<String, BTreeMap<u32, ShardMeta>>,
+    blocks: BTreeSet<(u64, String, u32)>,
+}
+
+impl Index {
+    fn insert(&mut self, meta: ShardMeta) {
+        if let Some(old) = self.blobs.get(&meta.commitment).and_then(|s| s.get(&meta.index)) {
+            self.blocks.remove(&(old.block_number, old.commitment.clone(), old.index));
+        }
+        self.blocks.insert((meta.block_number, meta.commitment.clone(), meta.index));
+        self.blobs.entry(meta.commitment.clone()).or_default().insert(meta.index, meta);
+    }
+
+    fn remove(&mut self, name: &str, index: u32) {
+        let Some(shards) = self.blobs.get_mut(name) else { return };
+        if let Some(meta) = shards.remove(&index) {
+            self.blocks.remove(&(meta.block_number, meta.commitment, index));
+        }
+        if shards.is_empty() {
+            self.blobs.remove(name);
+        }
+    }
+
+    fn remove_blob(&mut self, name: &str) -> BTreeMap<u32, ShardMeta> {
+        let shards = self.blobs.remove(name).unwrap_or_default();
+        for meta in shards.values() {
+            self.blocks.remove(&(meta.block_number, meta.commitment.clone(), meta.index));
+        }
+        shards
+    }
+}
+
+impl FsEngine {
+    /// Open the storage directory, indexing every stored shard
+    pub fn open(dir: &Path, node_id: u32) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let engine = Self {
+            dir: dir.to_path_buf(),
+            node_id,
+            index: RwLock::default(),
+            used_bytes: AtomicU64::new(0),
+        };
+        let mut chunks = Vec::new();
+        let mut index = Index::default();
+        let mut corrupt = BTreeSet::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if file_name.ends_with(".tmp")
+                && (file_name.starts_with("chunk_") || file_name.starts_with("meta_"))
+            {
+                // Left behind by a write that never completed
+                std::fs::remove_file(entry.path())?;
+            } else if let Some(key) = parse_file(&file_name, "chunk_", ".bin") {
+                chunks.push(key);
+            } else if let Some(key) = parse_file(&file_name, "meta_", ".json") {
+                match serde_json::from_slice::<ShardMeta>(&std::fs::read(entry.path())?) {
+                    Ok(meta) => {
+                        index.insert(meta);
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Quarantining shard {} of blob {}, its metadata is corrupt: {}",
+                            key.1, key.0, e
+                        );
+                        engine.quarantine(&entry.path())?;
+                        corrupt.insert(key);
+                    }
+                }
+            }
+        }
+
+        let mut stored = BTreeSet::new();
+        for (name, shard_index) in chunks {
+            if corrupt.contains(&(name.clone(), shard_index)) {
+                engine.quarantine(&engine.shard_path(&name, shard_index))?;
+                continue;
+            }
+            let known = index.blobs.get(&name).is_some_and(|s| s.contains_key(&shard_index));
+            if !known {
+                eprintln!(
+                    "Dropping shard {} of blob {}, it was not fully stored",
+                    shard_index, name
+                );
+                std::fs::remove_file(engine.shard_path(&name, shard_index))?;
+                continue;
+            }
+            stored.insert((name, shard_index));
+        }
+
+        // Metadata whose shard is gone is of no use
+        let orphaned: Vec<(String, u32)> = index
+            .blobs
+            .iter()
+            .flat_map(|(name, shards)| shards.keys().map(move |i| (name.clone(), *i)))
+            .filter(|key| !stored.contains(key))
+            .collect();
+        for (name, shard_index) in orphaned {
+            index.remove(&name, shard_index);
+            remove_if_exists(&engine.meta_path(&name, shard_index))?;
+        }
+
+        *engine.index.write().unwrap() = index;
+        engine.used_bytes.store(dir_size(dir)?, Ordering::Relaxed);
+        Ok(engine)
+    }
+
+    /// Move a file out of the store into the quarantine directory, keeping its name
+    fn quarantine(&self, path: &Path) -> std::io::Result<()> {
+        let quarantine_dir = self.dir.join(QUARANTINE_DIR);
+        std::fs::create_dir_all(&quarantine_dir)?;
+        let file_name = path.file_name().expect("store files have a name");
+        std::fs::rename(path, quarantine_dir.join(file_name))
+    }
+
+    /// Account for a file being replaced or removed, by the size it had before
+    fn release(&self, path: &Path) {
+        let len = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let _ = self.used_bytes.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+            Some(used.saturating_sub(len))
+        });
+    }
+
+    /// Remove a shard's files, accounting for their size
+    fn remove_files(&self, name: &str, index: u32) -> std::io::Result<()> {
+        for path in [self.meta_path(name, index), self.shard_path(name, index)] {
+            self.release(&path);
+            remove_if_exists(&path)?;
+        }
+        Ok(())
+    }
+
+    fn shard_path(&self, name: &str, index: u32) -> PathBuf {
+        self.dir.join(format!("chunk_{}_{}_{}.bin", name, self.node_id, index))
+    }
+
+    fn meta_path(&self, name: &str, index: u32) -> PathBuf {
+        self.dir.join(format!("meta_{}_{}_{}.json", name, self.node_id, index))
+    }
+}
+
+impl StorageEngine for FsEngine {
+    fn put(&self, meta: &ShardMeta, shard: &[u8]) -> std::io::Result<()> {
+        let shard_path = self.shard_path(&meta.commitment, meta.index);
+        let meta_path = self.meta_path(&meta.commitment, meta.index);
+        let meta_bytes = serde_json::to_vec(meta)?;
+        // Until the new metadata is written, the shard must not pass for the earlier copy
+        self.release(&meta_path);
+        remove_if_exists(&meta_path)?;
+        self.release(&shard_path);
+        write_atomic(&shard_path, shard)?;
+        self.used_bytes.fetch_add(shard.len() as u64, Ordering::Relaxed);
+        write_atomic(&meta_path, &meta_bytes)?;
+        self.used_bytes.fetch_add(meta_bytes.len() as u64, Ordering::Relaxed);
+        self.index.write().unwrap().insert(meta.clone());
+        Ok(())
+    }
+
+    fn get(&self, name: &str, index: u32) -> std::io::Result<Option<Vec<u8>>> {
+        if self.meta(name, index)?.is_none() {
+            return Ok(None);
+        }
+        match std::fs::read(self.shard_path(name, index)) {
+            Ok(shard) => Ok(Some(shard)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn meta(&self, name: &str, index: u32) -> std::io::Result<Option<ShardMeta>> {
+        let stored = self.index.read().unwrap();
+        Ok(stored.blobs.get(name).and_then(|shards| shards.get(&index)).cloned())
+    }
+
+    fn indices(&self, name: &str) -> std::io::Result<Vec<u32>> {
+        let index = self.index.read().unwrap();
+        Ok(index.blobs.get(name).map(|shards| shards.keys().copied().collect()).unwrap_or_default())
+    }
+
+    fn blobs(&self) -> std::io::Result<BTreeMap<String, Vec<u32>>> {
+        let index = self.index.read().unwrap();
+        Ok(index
+            .blobs
+            .iter()
+            .map(|(name, shards)| (name.clone(), shards.keys().copied().collect()))
+            .collect())
+    }
+
+    fn blobs_after(&self, after: &str, limit: usize) -> std::io::Result<Vec<(String, Vec<u32>)>> {
+        let index = self.index.read().unwrap();
+        Ok(index
+            .blobs
+            .range::<str, _>((Bound::Excluded(after), Bound::Unbounded))
+            .take(limit)
+            .map(|(name, shards)| (name.clone(), shards.keys().copied().collect()))
+            .collect())
+    }
+
+    fn shards_in_blocks(&self, blocks: RangeInclusive<u64>) -> std::io::Result<Vec<ShardMeta>> {
+        let index = self.index.read().unwrap();
+        let (from, to) = blocks.into_inner();
+        if from > to {
+            return Ok(Vec::new());
+        }
+        Ok(index
+            .blocks
+            .range((from, String::new(), 0)..)
+            .take_while(|(block_number, _, _)| *block_number <= to)
+            .filter_map(|(_, name, i)| index.blobs.get(name).and_then(|s| s.get(i)).cloned())
+            .collect())
+    }
+
+    fn remove(&self, name: &str, index: u32) -> std::io::Result<()> {
+        self.index.write().unwrap().remove(name, index);
+        self.remove_files(name, index)
+    }
+
+    fn remove_blob(&self, name: &str) -> std::io::Result<()> {
+        let shards = self.index.write().unwrap().remove_blob(name);
+        for index in shards.keys() {
+            self.remove_files(name, *index)?;
+        }
+        Ok(())
+    }
+
+    fn used_bytes(&self) -> u64 {
+        self.used_bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Size of the files directly in a directory
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+// Split `{prefix}{name}_{node_id}_{index}{suffix}` into the blob name and shard index
+fn parse_file(file_name: &str, prefix: &str, suffix: &str) -> Option<(String, u32)> {
+    let stem = file_name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    let mut parts = stem.rsplitn(3, '_');
+    let index = parts.next()?.parse().ok()?;
+    parts.next()?.parse::<u32>().ok()?;
+    Some((parts.next()?.to_string(), index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dispatch::BlobHeader, sequencer::coding::CodingScheme};
+    use reth::primitives::B256;
+
+    fn meta(name: &str, index: u32, shard: &[u8]) -> ShardMeta {
+        let header = BlobHeader {
+            name,
+            scheme: CodingScheme::default(),
+            block_number: 1,
+            block_hash: B256::ZERO,
+            sender: None,
+        };
+        ShardMeta::new(&header, index, shard, None)
+    }
+
+    #[test]
+    fn quarantines_shards_with_corrupt_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = FsEngine::open(dir.path(), 7).unwrap();
+        engine.put(&meta("blob", 0, b"first"), b"first").unwrap();
+        engine.put(&meta("blob", 1, b"second"), b"second").unwrap();
+        std::fs::write(engine.meta_path("blob", 1), b"{\"commitment\":").unwrap();
+        drop(engine);
+
+        let engine = FsEngine::open(dir.path(), 7).unwrap();
+        assert_eq!(engine.indices("blob").unwrap(), vec![0]);
+        assert_eq!(engine.get("blob", 0).unwrap().as_deref(), Some(&b"first"[..]));
+        let quarantine_dir = dir.path().join(QUARANTINE_DIR);
+        assert!(quarantine_dir.join("chunk_blob_7_1.bin").exists());
+        assert!(quarantine_dir.join("meta_blob_7_1.json").exists());
+        assert!(!engine.shard_path("blob", 1).exists());
+    }
+
+    #[test]
+    fn keeps_used_bytes_in_step_with_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = FsEngine::open(dir.path(), 7).unwrap();
+        let initial = engine.used_bytes();
+        assert_eq!(initial, dir_size(dir.path()).unwrap());
+
+        engine.put(&meta("a", 0, &[1; 100]), &[1; 100]).unwrap();
+        engine.put(&meta("a", 1, &[2; 100]), &[2; 100]).unwrap();
+        engine.put(&meta("b", 0, &[3; 50]), &[3; 50]).unwrap();
+        assert_eq!(engine.used_bytes(), dir_size(dir.path()).unwrap());
+
+        // Replacing a shard counts only its new size
+        engine.put(&meta("a", 1, &[4; 10]), &[4; 10]).unwrap();
+        assert_eq!(engine.used_bytes(), dir_size(dir.path()).unwrap());
+
+        engine.remove("b", 0).unwrap();
+        engine.remove_blob("a").unwrap();
+        assert_eq!(engine.used_bytes(), initial);
+
+        engine.put(&meta("c", 0, &[5; 20]), &[5; 20]).unwrap();
+        let used = engine.used_bytes();
+        drop(engine);
+        assert_eq!(FsEngine::open(dir.path(), 7).unwrap().used_bytes(), used);
+    }
+
+    #[test]
+    fn drops_shards_interrupted_while_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = FsEngine::open(dir.path(), 7).unwrap();
+        engine.put(&meta("a", 0, b"first"), b"first").unwrap();
+        engine.put(&meta("a", 1, b"second"), b"second").unwrap();
+        // Crashed while storing shard 1 again, after its old metadata was removed
+        std::fs::remove_file(engine.meta_path("a", 1)).unwrap();
+        std::fs::write(engine.shard_path("a", 1), b"replaced").unwrap();
+        // and while writing the bytes of another shard
+        let partial = engine.shard_path("b", 0).with_extension("tmp");
+        std::fs::write(&partial, b"partial").unwrap();
+        drop(engine);
+
+        let engine = FsEngine::open(dir.path(), 7).unwrap();
+        assert_eq!(engine.blobs().unwrap(), BTreeMap::from([("a".to_string(), vec![0])]));
+        assert!(!engine.shard_path("a", 1).exists());
+        assert!(!partial.exists());
+        assert_eq!(engine.used_bytes(), dir_size(dir.path()).unwrap());
+    }
+}
@@ -0,0 +1,200 @@
+use super::{ShardMeta, StorageEngine};
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionError,
+    TransactionalTree,
+};
+use std::{collections::BTreeMap, ops::RangeInclusive, path::Path};
+
+/// Keys of shard bytes, `s{name}\0{index}`
+const SHARD: u8 = b's';
+/// Keys of shard metadata, `m{name}\0{index}`
+const META: u8 = b'm';
+/// Keys of the block index, `b{block_number}{name}\0{index}`, with empty values
+const BLOCK: u8 = b'b';
+
+/// Shards in an embedded sled database
+///
+/// A shard, its metadata and its block index entry are written in one transaction, along with the
+/// removal of the index entry of an earlier copy, and the database is flushed before `put` returns.
+/// sled replays its log on open, so no recovery is needed beyond opening it.
+#[derive(Debug)]
+pub struct KvEngine {
+    db: sled::Db,
+}
+
+impl KvEngine {
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self { db: sled::open(dir.join("shards.sled"))? })
+    }
+
+    /// Run `f` in a transaction and flush it to disk
+    fn transact(
+        &self,
+        f: impl Fn(&TransactionalTree) -> ConflictableTransactionResult<(), std::io::Error>,
+    ) -> std::io::Result<()> {
+        self.db.transaction(f).map_err(|e| match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => e.into(),
+        })?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// Remove every key of a shard within a transaction
+fn remove_shard(
+    tx: &TransactionalTree,
+    name: &str,
+    index: u32,
+) -> ConflictableTransactionResult<(), std::io::Error> {
+    if let Some(meta) = tx.remove(shard_key(META, name, index))? {
+        let meta: ShardMeta = serde_json::from_slice(&meta)
+            .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+        tx.remove(block_key(meta.block_number, name, index))?;
+    }
+    tx.remove(shard_key(SHARD, name, index))?;
+    Ok(())
+}
+
+impl StorageEngine for KvEngine {
+    fn put(&self, meta: &ShardMeta, shard: &[u8]) -> std::io::Result<()> {
+        let (name, index) = (&meta.commitment, meta.index);
+        let meta_bytes = serde_json::to_vec(meta)?;
+        // The index entry of an earlier copy is read and removed in the same transaction, so a
+        // concurrent write of the shard cannot leave a stale one behind
+        self.transact(|tx| {
+            remove_shard(tx, name, index)?;
+            tx.insert(shard_key(SHARD, name, index), shard)?;
+            tx.insert(shard_key(META, name, index), meta_bytes.as_slice())?;
+            tx.insert(block_key(meta.block_number, name, index), &[][..])?;
+            Ok(())
+        })
+    }
+
+    fn get(&self, name: &str, index: u32) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.db.get(shard_key(SHARD, name, index))?.map(|shard| shard.to_vec()))
+    }
+
+    fn meta(&self, name: &str, index: u32) -> std::io::Result<Option<ShardMeta>> {
+        match self.db.get(shard_key(META, name, index))? {
+            Some(meta) => Ok(Some(serde_json::from_slice(&meta)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn indices(&self, name: &str) -> std::io::Result<Vec<u32>> {
+        let mut prefix = vec![META];
+        prefix.extend_from_slice(name.as_bytes());
+        prefix.push(0);
+
+        let mut indices = Vec::new();
+        for key in self.db.scan_prefix(prefix).keys() {
+            if let Some((_, index)) = parse_shard_key(&key?[1..]) {
+                indices.push(index);
+            }
+        }
+        Ok(indices)
+    }
+
+    fn blobs(&self) -> std::io::Result<BTreeMap<String, Vec<u32>>> {
+        // Keys are sorted, so are the indices of every blob
+        let mut blobs: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        for key in self.db.scan_prefix([META]).keys() {
+            if let Some((name, index)) = parse_shard_key(&key?[1..]) {
+                blobs.entry(name).or_default().push(index);
+            }
+        }
+        Ok(blobs)
+    }
+
+    fn blobs_after(&self, after: &str, limit: usize) -> std::io::Result<Vec<(String, Vec<u32>)>> {
+        // Keys of `after` itself end its name with 0, every later name sorts after a 1
+        let mut start = vec![META];
+        start.extend_from_slice(after.as_bytes());
+        start.push(1);
+
+        let mut blobs: Vec<(String, Vec<u32>)> = Vec::new();
+        for key in self.db.range(start..).keys() {
+            let key = key?;
+            if key[0] != META {
+                break;
+            }
+            let Some((name, index)) = parse_shard_key(&key[1..]) else { continue };
+            if let Some((last, indices)) = blobs.last_mut() {
+                if *last == name {
+                    indices.push(index);
+                    continue;
+                }
+            }
+            if blobs.len() == limit {
+                break;
+            }
+            blobs.push((name, vec![index]));
+        }
+        Ok(blobs)
+    }
+
+    fn shards_in_blocks(&self, blocks: RangeInclusive<u64>) -> std::io::Result<Vec<ShardMeta>> {
+        let (from, to) = blocks.into_inner();
+        let mut start = vec![BLOCK];
+        start.extend_from_slice(&from.to_be_bytes());
+
+        let mut shards = Vec::new();
+        for key in self.db.range(start..).keys() {
+            let key = key?;
+            if key.len() < 9 || key[0] != BLOCK {
+                break;
+            }
+            let block_number = u64::from_be_bytes(key[1..9].try_into().expect("8 bytes"));
+            if block_number > to {
+                break;
+            }
+            let Some((name, index)) = parse_shard_key(&key[9..]) else { continue };
+            if let Some(meta) = self.meta(&name, index)? {
+                shards.push(meta);
+            }
+        }
+        Ok(shards)
+    }
+
+    fn remove(&self, name: &str, index: u32) -> std::io::Result<()> {
+        self.transact(|tx| remove_shard(tx, name, index))
+    }
+
+    fn remove_blob(&self, name: &str) -> std::io::Result<()> {
+        let indices = self.indices(name)?;
+        self.transact(|tx| {
+            for index in &indices {
+                remove_shard(tx, name, *index)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn used_bytes(&self) -> u64 {
+        self.db.size_on_disk().unwrap_or_default()
+    }
+}
+
+fn shard_key(kind: u8, name: &str, index: u32) -> Vec<u8> {
+    let mut key = vec![kind];
+    key.extend_from_slice(name.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+fn block_key(block_number: u64, name: &str, index: u32) -> Vec<u8> {
+    let mut key = vec![BLOCK];
+    key.extend_from_slice(&block_number.to_be_bytes());
+    key.extend_from_slice(&shard_key(0, name, index)[1..]);
+    key
+}
+
+// Split `{name}\0{index}` into the blob name and shard index
+fn parse_shard_key(key: &[u8]) -> Option<(String, u32)> {
+    let (name, index) = key.split_last_chunk::<4>()?;
+    let name = name.strip_suffix(&[0])?;
+    Some((String::from_utf8(name.to_vec()).ok()?, u32::from_be_bytes(*index)))
+}
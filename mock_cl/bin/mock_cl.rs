@@ -4,8 +4,10 @@ use alloy::primitives::B256;
 use mock_cl::consensus_storage::{get_db_path, BlobConsensusStorage};
 
 use alloy_rpc_types_beacon::header::{BeaconBlockHeader, Header};
+use clap::Parser;
 use rusqlite::Result;
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -14,6 +16,26 @@ use warp::Filter;
 
 use serde::{Deserialize, Serialize};
 
+const INCLUSION_PROOF: [&str; 17] = [
+    "0x6f375622fe38528180b8bbce850131c5c287115fc0a19693a85073289b3aa1fe",
+    "0x4a97acf7425809951e2dfa23af457d1591f91a4c072fb5ae07a6c38b6ac02270",
+    "0xcdfe025837f134df085d20c9f4f48ba7469b6fe66dfd3ffe68086e3331f2ff3c",
+    "0xc78009fdf07fc56a11f122370658a353aaa542ed63e44c4bc15ff4cd105ab33c",
+    "0x536d98837f2dd165a55d5eeae91485954472d56f246df256bf3cae19352a123c",
+    "0x9efde052aa15429fae05bad4d0b1d7c64da64d03d7a1854a588c2cb8430c0d30",
+    "0xd88ddfeed400a8755596b21942c1497e114c302e6118290f91e6772976041fa1",
+    "0x87eb0ddba57e35f6d286673802a4af5975e22506c7cf4c64bb6be5ee11527f2c",
+    "0x26846476fd5fc54a5d43385167c95144f2643f533cc85bb9d16b782f8d7db193",
+    "0x506d86582d252405b840018792cad2bf1259f1ef5aa5f887e13cb2f0094f51e1",
+    "0xffff0ad7e659772f9534c195c815efc4014ef1e1daed4404c06385d11192e92b",
+    "0x6cf04127db05441cd833107a52be852868890e4317e6a02ab47683aa75964220",
+    "0x0600000000000000000000000000000000000000000000000000000000000000",
+    "0x792930bbd5baac43bcc798ee49aa8185ef76bb3b44ba62b91d86ae569e4bb535",
+    "0x527b1cda425c4bf8128c2ebcd1c9d9f4d507237067a59ce5815079553b36c6f7",
+    "0xdb56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71",
+    "0xe01c0837cb2d1b2dcb110929f0f3922b07d6712ae1e7ba65fda1eed7d79de4af",
+];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BeaconBlobBundle {
     /// Vec of individual blob data
@@ -52,8 +74,17 @@ struct SignedBeaconBlockHeader {
     signature: String,
 }
 
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Args {
+    /// Port the mock beacon API listens on
+    #[clap(long, env = "MOCK_CL_PORT", default_value_t = 4242)]
+    port: u16,
+}
+
 #[tokio::main]
 async fn main() {
+    let args = Args::parse();
     let conn = Arc::new(Mutex::new(
         BlobConsensusStorage::new(get_db_path()).expect("Failed to create BlobConsensusStorage"),
     ));
@@ -74,7 +105,7 @@ async fn main() {
 
     let routes = blob_route.or(all_blobs_route).or(delete_all_blobs_route);
 
-    warp::serve(routes).run(([127, 0, 0, 1], 4242)).await;
+    warp::serve(routes).run(([127, 0, 0, 1], args.port)).await;
 }
 
 fn with_db(
@@ -132,6 +163,16 @@ async fn serve_blob(
     match storage.get_blob(&param) {
         Ok(Some(row)) => {
             println!("Found blob for block: {}", param);
+            let body_root = match body_root(&row.kzg_commitment) {
+                Ok(body_root) => body_root,
+                Err(e) => {
+                    eprintln!("Invalid stored commitment: {:?}", e);
+                    return Ok(warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({"error": "Internal server error"})),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ));
+                }
+            };
             let response = json!({
               "data": [{
                 "index": "0",
@@ -144,29 +185,11 @@ async fn serve_blob(
                     "proposer_index": "110239".to_string(),
                     "parent_root": "0x83c2e78d90e9d4031c0de0db5782143ac38e0e7f41ad98f8b97dff90a270e6df".to_string(),
                     "state_root": "0x11122c310a39307f2d3150f9f368599dd8c5771786479314ed527002f10e6548".to_string(),
-                    "body_root": "0xe63dab4a3275db621ef3a3a34848d24049c61f6c0e93deaf6b179f0e8aee97b2".to_string(),
+                    "body_root": body_root,
                   },
                   "signature": "0x953e3b23dcc50ca430e7c9456a053ceba5990b1ee542c1631aa96a3cd998ba5bf0df86d027288ddac07e4100a312b44506b4c4ad8c6e1c6ce27d39d76627e1f729c3622130c97def6c76f39d29cbf1b1fe81204ed821c89389d8b61d22530455".to_string()
                 },
-                "kzg_commitment_inclusion_proof": [
-                "0x6f375622fe38528180b8bbce850131c5c287115fc0a19693a85073289b3aa1fe",
-                "0x4a97acf7425809951e2dfa23af457d1591f91a4c072fb5ae07a6c38b6ac02270",
-                "0xcdfe025837f134df085d20c9f4f48ba7469b6fe66dfd3ffe68086e3331f2ff3c",
-                "0xc78009fdf07fc56a11f122370658a353aaa542ed63e44c4bc15ff4cd105ab33c",
-                "0x536d98837f2dd165a55d5eeae91485954472d56f246df256bf3cae19352a123c",
-                "0x9efde052aa15429fae05bad4d0b1d7c64da64d03d7a1854a588c2cb8430c0d30",
-                "0xd88ddfeed400a8755596b21942c1497e114c302e6118290f91e6772976041fa1",
-                "0x87eb0ddba57e35f6d286673802a4af5975e22506c7cf4c64bb6be5ee11527f2c",
-                "0x26846476fd5fc54a5d43385167c95144f2643f533cc85bb9d16b782f8d7db193",
-                "0x506d86582d252405b840018792cad2bf1259f1ef5aa5f887e13cb2f0094f51e1",
-                "0xffff0ad7e659772f9534c195c815efc4014ef1e1daed4404c06385d11192e92b",
-                "0x6cf04127db05441cd833107a52be852868890e4317e6a02ab47683aa75964220",
-                "0x0600000000000000000000000000000000000000000000000000000000000000",
-                "0x792930bbd5baac43bcc798ee49aa8185ef76bb3b44ba62b91d86ae569e4bb535",
-                "0x527b1cda425c4bf8128c2ebcd1c9d9f4d507237067a59ce5815079553b36c6f7",
-                "0xdb56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71",
-                "0xe01c0837cb2d1b2dcb110929f0f3922b07d6712ae1e7ba65fda1eed7d79de4af"
-                ]
+                "kzg_commitment_inclusion_proof": INCLUSION_PROOF,
 
               }]
 
@@ -190,3 +213,29 @@ async fn serve_blob(
         }
     }
 }
+
+// Body root that the static inclusion proof commits the first blob's commitment to, so the
+// ExEx's inclusion check passes against the mock
+fn body_root(commitment_hex: &str) -> Result<String, hex::FromHexError> {
+    let commitment = hex::decode(commitment_hex)?;
+    if commitment.len() != 48 {
+        return Err(hex::FromHexError::InvalidStringLength);
+    }
+    let mut chunk = [0u8; 32];
+    chunk[..16].copy_from_slice(&commitment[32..]);
+    let mut value: [u8; 32] =
+        Sha256::new().chain_update(&commitment[..32]).chain_update(chunk).finalize().into();
+
+    // blob_kzg_commitments is field 11 of the body, index 0 of its list
+    let index: u64 = 11 << 13;
+    for (depth, sibling) in INCLUSION_PROOF.iter().enumerate() {
+        let sibling = hex::decode(sibling)?;
+        let hasher = Sha256::new();
+        value = if (index >> depth) & 1 == 1 {
+            hasher.chain_update(&sibling).chain_update(value).finalize().into()
+        } else {
+            hasher.chain_update(value).chain_update(&sibling).finalize().into()
+        };
+    }
+    Ok(format!("0x{}", hex::encode(value)))
+}